@@ -0,0 +1,70 @@
+use crate::utils::Color;
+
+/// Settings of the adaptive sampling.
+///
+/// Each pixel is sampled until the relative standard error of its mean luminance drops below threshold,
+/// taking at least min_samples and at most max_samples samples.
+/// Flat areas such as the skybox converge after a few samples, while noisy areas get up to max_samples samples.
+/// If sample_map_path is set, the number of samples taken for each pixel is saved as a grayscale image at this path.
+///
+/// # Example
+/// ```
+/// # use raytracer::adaptive::AdaptiveSampling;
+/// let adaptive_sampling = AdaptiveSampling {
+///     min_samples: 16,
+///     max_samples: 1024,
+///     threshold: 0.01,
+///     sample_map_path: Some(String::from("images/samples.png")),
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    pub threshold: f64,
+    pub sample_map_path: Option<String>,
+}
+
+// Luminance under which the error of a pixel is compared to this value instead of its luminance,
+// to avoid sampling nearly black pixels forever
+const MIN_LUMINANCE: f64 = 0.01;
+
+/// Running estimate of the mean and variance of the luminance of a pixel.
+pub(crate) struct PixelEstimator {
+    count: u32,
+    mean_luminance: f64,
+    m2: f64,
+}
+
+impl PixelEstimator {
+    pub fn new() -> Self {
+        PixelEstimator {
+            count: 0,
+            mean_luminance: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    /// Adds a sample to the estimate, using Welford's online algorithm for the variance.
+    pub fn add(&mut self, color: Color) {
+        self.count += 1;
+        let luminance = color.luminance();
+        let delta = luminance - self.mean_luminance;
+        self.mean_luminance += delta / self.count as f64;
+        self.m2 += delta * (luminance - self.mean_luminance);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns true if the relative standard error of the mean luminance is below the threshold.
+    pub fn has_converged(&self, threshold: f64) -> bool {
+        if self.count < 2 {
+            return false;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        let standard_error = (variance / self.count as f64).sqrt();
+        standard_error <= threshold * self.mean_luminance.max(MIN_LUMINANCE)
+    }
+}
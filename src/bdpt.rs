@@ -0,0 +1,497 @@
+use std::f64::consts::PI;
+
+use crate::camera::Camera;
+use crate::integrator::{Integrator, Splat};
+use crate::ray::Ray;
+use crate::sampler::{sample_unit_sphere, Sampler};
+use crate::scene::Scene;
+use crate::shapes::collide::HitRecord;
+use crate::utils::{dot, Color, Vec3};
+
+/// Bidirectional path tracer.
+///
+/// For each sample, a subpath is traced from the Camera and another one from a point of an emissive shape.
+/// Every vertex of the camera subpath is then connected to every vertex of the light subpath,
+/// and the vertices of the light subpath are connected to the Camera, splatting their light to the pixel they are seen in.
+/// All the ways of building the same path are combined with multiple importance sampling (balance heuristic).
+///
+/// Point, spot and directional lights and the skybox are only sampled from the camera subpath, like in the path tracer.
+pub(crate) struct BidirectionalPathTracer;
+
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+}
+
+/// Vertex of a subpath.
+struct Vertex {
+    kind: VertexKind,
+    point: Vec3,
+    /// Hit of the vertex, with the normal on its emitting side for light vertices. None for the camera vertices.
+    hit: Option<HitRecord>,
+    /// Direction of the ray arriving at the vertex
+    incoming: Vec3,
+    /// Throughput of the subpath up to this vertex, divided by the probability density of sampling it
+    beta: Color,
+    /// True if the next vertex of the subpath was sampled in a discrete direction, like the reflection of a mirror
+    delta: bool,
+    /// Probability density per unit area of sampling the vertex from the previous vertex of its subpath
+    pdf_fwd: f64,
+    /// Probability density per unit area of sampling the vertex from the next vertex, as if the subpath was traced the other way
+    pdf_rev: f64,
+}
+
+impl Vertex {
+    fn camera(point: Vec3) -> Self {
+        Vertex {
+            kind: VertexKind::Camera,
+            point,
+            hit: None,
+            incoming: Vec3::new(0.0, 0.0, 0.0),
+            beta: Color::new(255, 255, 255),
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn light(hit: HitRecord, beta: Color, pdf: f64) -> Self {
+        Vertex {
+            kind: VertexKind::Light,
+            point: hit.point,
+            hit: Some(hit),
+            incoming: Vec3::new(0.0, 0.0, 0.0),
+            beta,
+            delta: false,
+            pdf_fwd: pdf,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn surface(hit: HitRecord, incoming: Vec3, beta: Color) -> Self {
+        Vertex {
+            kind: VertexKind::Surface,
+            point: hit.point,
+            hit: Some(hit),
+            incoming,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    /// Returns true if the vertex can be connected to a vertex of the other subpath.
+    /// Materials mixing a mirror with a diffuse part can be, even when their mirror reflection was sampled.
+    fn is_connectible(&self) -> bool {
+        !self
+            .hit
+            .as_ref()
+            .is_some_and(|hit| hit.material.is_specular())
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.hit
+            .as_ref()
+            .is_some_and(|hit| hit.material.is_emissive())
+    }
+
+    /// Returns the light emitted by the vertex toward the point.
+    fn emitted(&self, point: &Vec3) -> Color {
+        match &self.hit {
+            Some(hit) if dot(&(*point - self.point), &hit.normal) > 0.0 => hit.material.emit(),
+            _ => Color::new(0, 0, 0),
+        }
+    }
+
+    /// Returns the factor applied to the light leaving the vertex toward the point, including the cosine at the vertex.
+    /// It is the BSDF of the surface vertices.
+    fn connection_factor(&self, point: &Vec3) -> Color {
+        let hit = match &self.hit {
+            Some(hit) => hit,
+            None => return Color::new(0, 0, 0),
+        };
+        let direction = *point - self.point;
+        match self.kind {
+            // The emitted light is already in the throughput of the light vertices
+            VertexKind::Light => {
+                let cos_theta = dot(&direction.normalize(), &hit.normal);
+                Color::new(255, 255, 255) * cos_theta.max(0.0)
+            }
+            _ => {
+                let ray = Ray::new(self.point - self.incoming, self.incoming);
+                hit.material.eval(&ray, hit, &direction)
+            }
+        }
+    }
+
+    /// Converts a density in solid angle of the direction from this vertex to next into a density per unit area at next.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let to_next = next.point - self.point;
+        let distance_squared = to_next.len_squared();
+        if distance_squared <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        // Points inside a medium have no surface to project the density on
+        if let Some(hit) = next
+            .hit
+            .as_ref()
+            .filter(|hit| !hit.material.is_volumetric())
+        {
+            pdf *= dot(&hit.normal, &to_next).abs() / distance_squared.sqrt();
+        }
+        pdf
+    }
+
+    /// Returns the density per unit area with which next is sampled from this vertex, reached from prev.
+    fn pdf(&self, camera: &Camera, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = next.point - self.point;
+        let pdf = match (&self.kind, &self.hit, prev) {
+            (VertexKind::Camera, _, _) => camera.pdf_direction(&self.point, &direction),
+            (VertexKind::Light, _, _) => return self.pdf_emission(next),
+            (VertexKind::Surface, Some(hit), Some(prev)) => {
+                let ray = Ray::new(prev.point, self.point - prev.point);
+                hit.material.pdf(&ray, hit, &direction)
+            }
+            _ => 0.0,
+        };
+        self.convert_density(pdf, next)
+    }
+
+    /// Returns the density per unit area with which next is sampled by the emission of the light at this vertex.
+    fn pdf_emission(&self, next: &Vertex) -> f64 {
+        let hit = match &self.hit {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        let cos_theta = dot(&(next.point - self.point).normalize(), &hit.normal);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        self.convert_density(cos_theta / PI, next)
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn get_ray_color(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        ray: Ray,
+        splats: &mut Vec<Splat>,
+    ) -> Color {
+        let max_depth = scene.get_config().max_ray_bounce as usize;
+        let mut color = Color::new(0, 0, 0);
+
+        // Camera subpath
+        let mut camera_path = vec![Vertex::camera(ray.origin)];
+        let pdf = camera.pdf_direction(&ray.origin, &ray.direction);
+        let escaped = random_walk(
+            scene,
+            camera,
+            sampler,
+            ray,
+            Color::new(255, 255, 255),
+            pdf,
+            max_depth + 2,
+            &mut camera_path,
+        );
+        if let Some(beta) = escaped {
+            color += beta * scene.get_skybox_color();
+        }
+
+        // The point, spot and directional lights can only be sampled from the camera subpath
+        for vertex in camera_path
+            .iter()
+            .skip(1)
+            .filter(|vertex| vertex.is_connectible())
+        {
+            let hit = vertex.hit.as_ref().unwrap();
+            let ray = Ray::new(vertex.point - vertex.incoming, vertex.incoming);
+            color += vertex.beta * scene.sample_punctual_lights(&ray, hit);
+        }
+
+        // Light subpath
+        let mut light_path = vec![];
+        if let Some((hit, pdf)) = scene.sample_light_surface(sampler) {
+            let emitted = hit.material.emit();
+            let normal = hit.normal;
+            let origin = Vertex::light(hit, emitted / pdf, pdf);
+            let direction = (normal + sample_unit_sphere(sampler.next_2d())).normalize();
+            let cos_theta = dot(&direction, &normal);
+            let beta = origin.beta * PI;
+            let ray = Ray::new(origin.point, direction);
+            light_path.push(origin);
+            if cos_theta > 0.0 {
+                random_walk(
+                    scene,
+                    camera,
+                    sampler,
+                    ray,
+                    beta,
+                    cos_theta / PI,
+                    max_depth + 1,
+                    &mut light_path,
+                );
+            }
+        }
+
+        // Connect the subpaths with every strategy
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                if s + t < 2 || s + t - 2 > max_depth {
+                    continue;
+                }
+                color += connect(
+                    scene,
+                    camera,
+                    sampler,
+                    &light_path,
+                    &camera_path,
+                    s,
+                    t,
+                    splats,
+                );
+            }
+        }
+
+        color
+    }
+}
+
+/// Extends the subpath from the ray, until it leaves the Scene, is absorbed or reaches max_vertices vertices.
+/// pdf is the density in solid angle with which the direction of the ray was sampled.
+/// Returns the throughput of the subpath if it left the Scene.
+#[allow(clippy::too_many_arguments)]
+fn random_walk(
+    scene: &Scene,
+    camera: &Camera,
+    sampler: &mut dyn Sampler,
+    ray: Ray,
+    beta: Color,
+    pdf: f64,
+    max_vertices: usize,
+    path: &mut Vec<Vertex>,
+) -> Option<Color> {
+    let mut ray = ray;
+    let mut beta = beta;
+    let mut pdf_fwd = pdf;
+    let from_camera = matches!(path[0].kind, VertexKind::Camera);
+
+    while path.len() < max_vertices {
+        let first_bounce = from_camera && path.len() == 1;
+        let hit = match scene.get_intersection(camera, &ray, first_bounce) {
+            Some(hit) => hit,
+            None => return Some(beta),
+        };
+        beta *= hit.material.transmittance(&ray, &hit);
+
+        let mut vertex = Vertex::surface(hit, ray.direction, beta);
+        vertex.pdf_fwd = path[path.len() - 1].convert_density(pdf_fwd, &vertex);
+        path.push(vertex);
+        if path.len() >= max_vertices {
+            break;
+        }
+
+        let n = path.len();
+        let hit = path[n - 1].hit.as_ref().unwrap();
+        let material = hit.material.clone();
+        let bouncing_ray = match material.scatter(&ray, hit, sampler) {
+            Some(bouncing_ray) => bouncing_ray,
+            None => break,
+        };
+
+        let pdf = material.pdf(&ray, hit, &bouncing_ray.direction);
+        let pdf_rev = if material.is_specular() || pdf <= 0.0 {
+            beta *= material.get_attenuation(hit);
+            path[n - 1].delta = true;
+            pdf_fwd = 0.0;
+            0.0
+        } else {
+            beta *= material.eval(&ray, hit, &bouncing_ray.direction) / pdf;
+            pdf_fwd = pdf;
+            let reversed = Ray::new(bouncing_ray.at(1.0), -bouncing_ray.direction);
+            material.pdf(&reversed, hit, &-ray.direction)
+        };
+        path[n - 2].pdf_rev = path[n - 1].convert_density(pdf_rev, &path[n - 2]);
+        if beta.is_black() {
+            break;
+        }
+        ray = bouncing_ray;
+    }
+    None
+}
+
+/// Computes the contribution of the path made of the s first vertices of the light subpath
+/// and the t first vertices of the camera subpath, weighted with multiple importance sampling.
+/// When t is 1, the light subpath is connected to a point sampled on the len and the result is pushed in splats.
+#[allow(clippy::too_many_arguments)]
+fn connect(
+    scene: &Scene,
+    camera: &Camera,
+    sampler: &mut dyn Sampler,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+    splats: &mut Vec<Splat>,
+) -> Color {
+    let black = Color::new(0, 0, 0);
+
+    if s == 0 {
+        // The camera subpath hit an emissive shape by itself
+        let vertex = &camera_path[t - 1];
+        if !vertex.is_emissive() {
+            return black;
+        }
+        let color = vertex.beta * vertex.emitted(&camera_path[t - 2].point);
+        if color.is_black() {
+            return black;
+        }
+        return color * mis_weight(scene, camera, light_path, camera_path, s, t, None);
+    }
+
+    let light_vertex = &light_path[s - 1];
+    if !light_vertex.is_connectible() {
+        return black;
+    }
+
+    if t == 1 {
+        // Connect the light subpath to the len of the camera
+        let connection = match camera.connect(&light_vertex.point, sampler.next_2d()) {
+            Some(connection) => connection,
+            None => return black,
+        };
+        let color = light_vertex.beta
+            * light_vertex.connection_factor(&connection.point)
+            * connection.importance;
+        if color.is_black() {
+            return black;
+        }
+        let color = color * scene.transmittance_between(&light_vertex.point, &connection.point);
+        if color.is_black() {
+            return black;
+        }
+        let camera_vertex = Vertex::camera(connection.point);
+        let weight = mis_weight(
+            scene,
+            camera,
+            light_path,
+            camera_path,
+            s,
+            t,
+            Some(&camera_vertex),
+        );
+        let config = scene.get_config();
+        splats.push(Splat {
+            x: connection.u * config.width as f64,
+            y: (1.0 - connection.v) * config.height as f64,
+            color: color * weight,
+        });
+        return black;
+    }
+
+    let camera_vertex = &camera_path[t - 1];
+    if !camera_vertex.is_connectible() {
+        return black;
+    }
+    let distance_squared = (light_vertex.point - camera_vertex.point).len_squared();
+    let color = light_vertex.beta
+        * light_vertex.connection_factor(&camera_vertex.point)
+        * camera_vertex.connection_factor(&light_vertex.point)
+        * camera_vertex.beta
+        / distance_squared;
+    if color.is_black() {
+        return black;
+    }
+    let color = color * scene.transmittance_between(&light_vertex.point, &camera_vertex.point);
+    if color.is_black() {
+        return black;
+    }
+    color * mis_weight(scene, camera, light_path, camera_path, s, t, None)
+}
+
+/// Returns the weight of the strategy (s,t) with the balance heuristic, among all the strategies that can build the same path.
+/// sampled_camera replaces the first vertex of the camera subpath when t is 1.
+fn mis_weight(
+    scene: &Scene,
+    camera: &Camera,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+    sampled_camera: Option<&Vertex>,
+) -> f64 {
+    // Densities and delta flags of the vertices of the path, updated for the connection of the two subpaths
+    let mut camera_pdfs: Vec<(f64, f64, bool)> = camera_path[..t]
+        .iter()
+        .map(|vertex| (vertex.pdf_fwd, vertex.pdf_rev, vertex.delta))
+        .collect();
+    let mut light_pdfs: Vec<(f64, f64, bool)> = light_path[..s]
+        .iter()
+        .map(|vertex| (vertex.pdf_fwd, vertex.pdf_rev, vertex.delta))
+        .collect();
+
+    let pt = match sampled_camera {
+        Some(vertex) if t == 1 => vertex,
+        _ => &camera_path[t - 1],
+    };
+    let pt_minus = if t > 1 {
+        Some(&camera_path[t - 2])
+    } else {
+        None
+    };
+    let qs = if s > 0 {
+        Some(&light_path[s - 1])
+    } else {
+        None
+    };
+    let qs_minus = if s > 1 {
+        Some(&light_path[s - 2])
+    } else {
+        None
+    };
+
+    camera_pdfs[t - 1].1 = match (qs, pt_minus) {
+        (Some(qs), _) => qs.pdf(camera, qs_minus, pt),
+        (None, Some(pt_minus)) => scene.light_surface_pdf(&pt_minus.point, &pt.point),
+        (None, None) => 0.0,
+    };
+    camera_pdfs[t - 1].2 = false;
+    if let Some(pt_minus) = pt_minus {
+        camera_pdfs[t - 2].1 = match qs {
+            Some(qs) => pt.pdf(camera, Some(qs), pt_minus),
+            None => pt.pdf_emission(pt_minus),
+        };
+    }
+    if let Some(qs) = qs {
+        light_pdfs[s - 1].1 = pt.pdf(camera, pt_minus, qs);
+        light_pdfs[s - 1].2 = false;
+        if let Some(qs_minus) = qs_minus {
+            light_pdfs[s - 2].1 = qs.pdf(camera, Some(pt), qs_minus);
+        }
+    }
+
+    // Null densities come from delta vertices, which are skipped anyway
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        ratio *= remap(camera_pdfs[i].1) / remap(camera_pdfs[i].0);
+        if !camera_pdfs[i].2 && !camera_pdfs[i - 1].2 {
+            sum += ratio;
+        }
+    }
+    ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap(light_pdfs[i].1) / remap(light_pdfs[i].0);
+        let delta_light = i > 0 && light_pdfs[i - 1].2;
+        if !light_pdfs[i].2 && !delta_light {
+            sum += ratio;
+        }
+    }
+    1.0 / (1.0 + sum)
+}
@@ -1,8 +1,16 @@
+use rand::Rng;
 use raytracer::camera::Camera;
+use raytracer::color_management::TransferFunction;
+use raytracer::filter::Filter;
+use raytracer::integrator::IntegratorKind;
 use raytracer::material::{Dielectric, Diffuse, DiffuseLight, DiffuseMetal, Metal};
+use raytracer::sampler::SamplerKind;
 use raytracer::scene::{Scene, SceneBuilder};
 use raytracer::shapes::mesh::STLMesh;
 use raytracer::shapes::sphere::Sphere;
+use raytracer::texture::Checker;
+use raytracer::tile::TileOrder;
+use raytracer::tone_mapping::ToneMapping;
 use raytracer::utils::{Color, Vec3};
 use raytracer::{render, Config};
 
@@ -12,35 +20,36 @@ fn get_random_scene(config: Config) -> Scene {
 
     // Ground
     scene.add_shape(
-        Sphere::new(Vec3::new(0.0, -1000.0, -1.0), 1000.0)
-            .set_material(Diffuse::new(Color::new(150, 150, 150))),
+        Sphere::new(Vec3::new(0.0, -1000.0, -1.0), 1000.0).set_material(Diffuse::new(
+            Checker::new(Color::new(50, 60, 40), Color::new(200, 200, 200), 1.0),
+        )),
     );
 
     // Random spheres
     for x in -11..11 {
         for z in -11..11 {
+            let rng = scene.get_rng();
             let center = Vec3::new(
-                x as f64 + 0.9 * rand::random::<f64>(),
+                x as f64 + 0.9 * rng.gen::<f64>(),
                 0.2,
-                z as f64 + 0.9 * rand::random::<f64>(),
+                z as f64 + 0.9 * rng.gen::<f64>(),
             );
 
             if (center - Vec3::new(4.0, 0.2, 0.0)).len() <= 0.9 {
                 continue;
             }
 
-            let choose_material = rand::random::<f64>();
+            let choose_material = rng.gen::<f64>();
+            let color = Color::random(rng);
+            let fuzziness = rng.gen::<f64>();
 
             match choose_material {
                 c if c < 0.8 => {
-                    scene.add_shape(
-                        Sphere::new(center, 0.2).set_material(Diffuse::new(Color::random())),
-                    );
+                    scene.add_shape(Sphere::new(center, 0.2).set_material(Diffuse::new(color)));
                 }
                 c if c < 0.95 => {
                     scene.add_shape(
-                        Sphere::new(center, 0.2)
-                            .set_material(Metal::new(Color::random(), rand::random())),
+                        Sphere::new(center, 0.2).set_material(Metal::new(color, fuzziness)),
                     );
                 }
                 _ => {
@@ -77,8 +86,9 @@ fn get_light_scene(config: Config) -> Scene {
 
     // Ground
     scene.add_shape(
-        Sphere::new(Vec3::new(0.0, -1000.0, -1.0), 1000.0)
-            .set_material(Diffuse::new(Color::new(150, 150, 150))),
+        Sphere::new(Vec3::new(0.0, -1000.0, -1.0), 1000.0).set_material(Diffuse::new(
+            Checker::new(Color::new(50, 60, 40), Color::new(200, 200, 200), 1.0),
+        )),
     );
 
     // A metallic sphere on the ground
@@ -144,9 +154,25 @@ fn main() {
         width: 640,
         height: 400,
         output_path: String::from("images/test.png"),
+        output_format: None,
         anti_aliasing: Some(10),
+        filter: Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        },
+        sampler: SamplerKind::Sobol,
+        seed: 0,
+        integrator: IntegratorKind::NeePathTracing,
         max_ray_bounce: 20,
-        gamma_correction: 1.0,
+        russian_roulette_depth: Some(5),
+        transfer_function: TransferFunction::Srgb,
+        exposure: 0.0,
+        tone_mapping: ToneMapping::Aces,
+        tile_size: 32,
+        tile_order: TileOrder::Spiral,
+        progressive_passes: None,
+        adaptive_sampling: None,
     };
     let aspect_ratio = config.width as f64 / config.height as f64;
 
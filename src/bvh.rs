@@ -8,6 +8,7 @@ use crate::{
 
 /// Axis Aligned Bounding Box.
 /// It is defined by its lowest and highest corners
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Copy)]
 pub struct AABB {
     min: Vec3,
@@ -36,16 +37,31 @@ impl AABB {
 
     /// Returns if the AABB was hit
     pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.get_hit_interval(ray, t_min, t_max).is_some()
+    }
+
+    /// Returns the interval of t, clipped to [t_min, t_max], during which the ray is inside the AABB.
+    pub fn get_hit_interval(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
         let (mut min, mut max) = (t_min, t_max);
         for i in 0..3 {
             let (t0, t1) = get_interval(self.min[i], self.max[i], ray.origin[i], ray.direction[i]);
             min = t0.max(min);
             max = t1.min(max);
             if max <= min {
-                return false;
+                return None;
             }
         }
-        return true;
+        Some((min, max))
+    }
+
+    /// Returns the lowest corner of the AABB.
+    pub fn get_min(&self) -> Vec3 {
+        self.min
+    }
+
+    /// Returns the highest corner of the AABB.
+    pub fn get_max(&self) -> Vec3 {
+        self.max
     }
 
     fn get_longer_axis(&self) -> usize {
@@ -87,23 +103,24 @@ pub fn surrounding_box(a: AABB, b: AABB) -> AABB {
 }
 
 type ArcCollide = Arc<dyn Collide + Send + Sync>;
+type BoxComparator = Box<dyn FnMut(&ArcCollide, &ArcCollide) -> Ordering>;
 
 pub fn get_bounding_box<T>(objects: &[Arc<T>]) -> AABB
 where
     T: ?Sized,
     T: Collide + Send + Sync,
 {
-    if objects.len() < 1 {
+    if objects.is_empty() {
         panic!("Please provide a vector with at least one element");
     }
     let mut aabb = objects[0].get_bounding_box().unwrap();
-    for i in 1..objects.len() {
-        aabb = surrounding_box(aabb, objects[i].get_bounding_box().unwrap());
+    for object in objects.iter().skip(1) {
+        aabb = surrounding_box(aabb, object.get_bounding_box().unwrap());
     }
     aabb
 }
 
-fn box_compare_on(axis: usize) -> Box<dyn FnMut(&ArcCollide, &ArcCollide) -> Ordering> {
+fn box_compare_on(axis: usize) -> BoxComparator {
     Box::new(move |a: &ArcCollide, b: &ArcCollide| {
         if a.get_bounding_box().unwrap().min[axis] < b.get_bounding_box().unwrap().min[axis] {
             Ordering::Less
@@ -115,6 +132,7 @@ fn box_compare_on(axis: usize) -> Box<dyn FnMut(&ArcCollide, &ArcCollide) -> Ord
 
 /// Bounding Volume Hierarchy.
 /// Tree like structure to divide the scene into AABB and speed up ray/object intersection calculations.
+#[allow(clippy::upper_case_acronyms)]
 pub struct BVH {
     left: ArcCollide,
     right: ArcCollide,
@@ -128,25 +146,34 @@ impl Collide for BVH {
         }
 
         let hit_left = self.left.get_intersection(ray, t_min, t_max);
-        let t_max = if hit_left.is_some() {
-            hit_left.as_ref().unwrap().t
-        } else {
-            t_max
+        let t_max = match &hit_left {
+            Some(hit) => hit.t,
+            None => t_max,
         };
         let hit_right = self.right.get_intersection(ray, t_min, t_max);
 
         if hit_right.is_some() {
-            return hit_right;
-        } else if hit_left.is_some() {
-            return hit_left;
+            hit_right
         } else {
-            return None;
+            hit_left
         }
     }
 
     fn get_bounding_box(&self) -> Option<AABB> {
         Some(self.aabb)
     }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if !self.aabb.hit(ray, t_min, t_max) {
+            return 1.0;
+        }
+        let transmittance = self.left.transmittance(ray, t_min, t_max);
+        // Leafs holding a single object have it on both sides
+        if transmittance <= 0.0 || Arc::ptr_eq(&self.left, &self.right) {
+            return transmittance;
+        }
+        transmittance * self.right.transmittance(ray, t_min, t_max)
+    }
 }
 
 impl BVH {
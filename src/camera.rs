@@ -1,7 +1,6 @@
-use rand_distr::{Distribution, UnitDisc};
-
 use crate::ray::Ray;
-use crate::utils::{cross, Base, Vec3};
+use crate::sampler::{sample_unit_disk, Sampler};
+use crate::utils::{cross, dot, Base, Vec3};
 
 struct Viewport {
     width: f64,
@@ -10,10 +9,24 @@ struct Viewport {
     lower_left_corner: Vec3,
 }
 
+/// Connection of a point of the Scene to the len of the Camera.
+pub(crate) struct CameraConnection {
+    /// Point of the len the point of the Scene is connected to
+    pub point: Vec3,
+    /// Coordinates of the viewport seen from the len along the connection
+    pub u: f64,
+    pub v: f64,
+    /// Factor converting the light arriving at the len along the connection to the color of the image
+    pub importance: f64,
+}
+
 /// A camera that can render a scene.
 ///
 /// # Example
 /// ```
+/// # use raytracer::camera::Camera;
+/// # use raytracer::utils::Vec3;
+/// # let config = raytracer::Config::default();
 /// let aspect_ratio = config.width as f64 / config.height as f64;
 ///
 /// let camera = Camera::new(
@@ -145,9 +158,11 @@ impl Camera {
         self
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        let r: [f64; 2] = UnitDisc.sample(&mut rand::thread_rng());
-        let offset = (self.base.u() * r[0] + self.base.v() * r[1]) * self.len_radius;
+    /// Returns a ray going through the point (u,v) of the viewport, (0,0) being its lower left corner and (1,1) its upper right corner.
+    /// The origin of the ray on the len is chosen with the next 2D sample of the Sampler.
+    pub fn get_ray(&self, u: f64, v: f64, sampler: &mut dyn Sampler) -> Ray {
+        let r = sample_unit_disk(sampler.next_2d());
+        let offset = (self.base.u() * r.0 + self.base.v() * r.1) * self.len_radius;
         let origin = self.position + offset;
         let direction = (self.viewport.lower_left_corner
             + self.base.u() * self.viewport.width * self.focus * u
@@ -158,6 +173,60 @@ impl Camera {
         Ray::new(origin, direction)
     }
 
+    /// Samples a point on the len of the Camera with the 2D sample and connects the point of the Scene to it.
+    /// Returns None if the point isn't seen by the Camera.
+    pub(crate) fn connect(&self, point: &Vec3, sample: (f64, f64)) -> Option<CameraConnection> {
+        let r = sample_unit_disk(sample);
+        let len_point =
+            self.position + (self.base.u() * r.0 + self.base.v() * r.1) * self.len_radius;
+        let to_point = *point - len_point;
+        let (u, v) = self.project(&len_point, &to_point)?;
+        let distance = to_point.len();
+        if distance < self.near_clip_plane || distance > self.far_clip_plane {
+            return None;
+        }
+        let cos_theta = dot(&to_point, &-self.base.w()) / distance;
+        Some(CameraConnection {
+            point: len_point,
+            u,
+            v,
+            importance: self.pdf_cos_theta(cos_theta) / (distance * distance),
+        })
+    }
+
+    /// Returns the density, in solid angle, with which the rays leaving the point origin of the len take the direction.
+    pub(crate) fn pdf_direction(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        match self.project(origin, direction) {
+            Some(_) => self.pdf_cos_theta(dot(&direction.normalize(), &-self.base.w())),
+            None => 0.0,
+        }
+    }
+
+    /// The points of the viewport are sampled uniformly, which gives a density in solid angle of 1 / (A cos³θ)
+    /// for the directions making an angle θ with the axis of the Camera, A being the area of the viewport at distance 1.
+    fn pdf_cos_theta(&self, cos_theta: f64) -> f64 {
+        let area = self.viewport.width * self.viewport.height;
+        1.0 / (area * cos_theta * cos_theta * cos_theta)
+    }
+
+    /// Returns the coordinates (u,v) of the viewport seen along the direction from the point origin of the len,
+    /// or None if the direction is outside of the viewport.
+    fn project(&self, origin: &Vec3, direction: &Vec3) -> Option<(f64, f64)> {
+        let direction = direction.normalize();
+        let cos_theta = dot(&direction, &-self.base.w());
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let focus_point = *origin + direction * (self.focus / cos_theta);
+        let offset = focus_point - self.viewport.lower_left_corner;
+        let u = dot(&offset, &self.base.u()) / (self.viewport.width * self.focus);
+        let v = dot(&offset, &self.base.v()) / (self.viewport.height * self.focus);
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+        Some((u, v))
+    }
+
     fn set_lower_left_corner(&mut self) {
         self.viewport.lower_left_corner = self.position
             - (self.base.u() * self.viewport.width * self.focus) / 2.0
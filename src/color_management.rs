@@ -0,0 +1,51 @@
+/// Transfer function used to encode the linear colors of a render into the values stored in an 8 bits image.
+///
+/// All the computations of the renderer are done in a linear RGB working space with sRGB primaries.
+/// Srgb applies the piecewise sRGB transfer curve, which is what image viewers expect for PNG and JPEG files.
+/// Gamma applies a plain power curve with the given gamma, encoding a value v as v^(1/gamma).
+/// Linear stores the linear values directly.
+///
+/// # Example
+/// ```
+/// # use raytracer::color_management::TransferFunction;
+/// assert!((TransferFunction::Srgb.encode(1.0) - 1.0).abs() < 1e-9);
+/// assert!((TransferFunction::Srgb.encode(0.18) - 0.46).abs() < 0.01);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferFunction {
+    Srgb,
+    Gamma(f64),
+    Linear,
+}
+
+impl TransferFunction {
+    /// Encodes a linear value between 0.0 and 1.0.
+    pub fn encode(&self, value: f64) -> f64 {
+        match self {
+            TransferFunction::Srgb => {
+                if value <= 0.0031308 {
+                    12.92 * value
+                } else {
+                    1.055 * value.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            TransferFunction::Gamma(gamma) => value.powf(1.0 / gamma),
+            TransferFunction::Linear => value,
+        }
+    }
+
+    /// Decodes an encoded value between 0.0 and 1.0 back to a linear value.
+    pub fn decode(&self, value: f64) -> f64 {
+        match self {
+            TransferFunction::Srgb => {
+                if value <= 0.04045 {
+                    value / 12.92
+                } else {
+                    ((value + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            TransferFunction::Gamma(gamma) => value.powf(*gamma),
+            TransferFunction::Linear => value,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::filter::Filter;
+use crate::framebuffer::FrameBuffer;
+use crate::tile::Tile;
+use crate::utils::Color;
+
+/// Accumulates the samples of a render, splatting each of them to all the pixels covered by the reconstruction filter.
+///
+/// A Film covers a rectangular area of the image starting at (x0,y0). Tiles are rendered in their own Film,
+/// extended by the radius of the filter so that the samples close to their borders reach the neighbouring pixels,
+/// and are then merged in the Film of the whole image.
+///
+/// Integrators tracing paths from the lights also splat light to arbitrary pixels of the image.
+/// These splats are summed without filtering, and scaled by the average number of samples per pixel of the image.
+pub(crate) struct Film {
+    x0: i64,
+    y0: i64,
+    width: u32,
+    height: u32,
+    filter: Filter,
+    weighted_sums: Vec<Color>,
+    weights: Vec<f64>,
+    sample_counts: Vec<u32>,
+    splats: Vec<Color>,
+}
+
+impl Film {
+    /// Creates a new Film covering an image of size width x height.
+    pub fn new(width: u32, height: u32, filter: Filter) -> Self {
+        Film::with_area(0, 0, width, height, filter)
+    }
+
+    /// Creates a new Film covering the tile extended by the radius of the filter, clipped to the image bounds.
+    pub fn for_tile(tile: &Tile, image_width: u32, image_height: u32, filter: Filter) -> Self {
+        let padding = filter.radius().ceil() as i64;
+        let x0 = (tile.x as i64 - padding).max(0);
+        let y0 = (tile.y as i64 - padding).max(0);
+        let x1 = ((tile.x + tile.width) as i64 + padding).min(image_width as i64);
+        let y1 = ((tile.y + tile.height) as i64 + padding).min(image_height as i64);
+        Film::with_area(x0, y0, (x1 - x0) as u32, (y1 - y0) as u32, filter)
+    }
+
+    fn with_area(x0: i64, y0: i64, width: u32, height: u32, filter: Filter) -> Self {
+        let n_pixels = (width * height) as usize;
+        Film {
+            x0,
+            y0,
+            width,
+            height,
+            filter,
+            weighted_sums: vec![Color::new(0, 0, 0); n_pixels],
+            weights: vec![0.0; n_pixels],
+            sample_counts: vec![0; n_pixels],
+            splats: vec![Color::new(0, 0, 0); n_pixels],
+        }
+    }
+
+    /// Adds a sample of the given color taken at the position (x,y) of the image, in pixels.
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color) {
+        let radius = self.filter.radius();
+        let x_min = ((x - 0.5 - radius).ceil() as i64).max(self.x0);
+        let x_max = ((x - 0.5 + radius).floor() as i64).min(self.x0 + self.width as i64 - 1);
+        let y_min = ((y - 0.5 - radius).ceil() as i64).max(self.y0);
+        let y_max = ((y - 0.5 + radius).floor() as i64).min(self.y0 + self.height as i64 - 1);
+
+        for pixel_y in y_min..=y_max {
+            for pixel_x in x_min..=x_max {
+                let weight = self
+                    .filter
+                    .evaluate(pixel_x as f64 + 0.5 - x, pixel_y as f64 + 0.5 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = self.index(pixel_x, pixel_y);
+                self.weighted_sums[index] += color * weight;
+                self.weights[index] += weight;
+            }
+        }
+
+        let (pixel_x, pixel_y) = (x.floor() as i64, y.floor() as i64);
+        if self.contains(pixel_x, pixel_y) {
+            let index = self.index(pixel_x, pixel_y);
+            self.sample_counts[index] += 1;
+        }
+    }
+
+    /// Adds light splatted at the position (x,y) of the image, in pixels.
+    pub fn add_splat(&mut self, x: f64, y: f64, color: Color) {
+        let (pixel_x, pixel_y) = (x.floor() as i64, y.floor() as i64);
+        if self.contains(pixel_x, pixel_y) {
+            let index = self.index(pixel_x, pixel_y);
+            self.splats[index] += color;
+        }
+    }
+
+    /// Adds the samples accumulated by another Film, whose area must be inside the area of this one.
+    pub fn merge(&mut self, other: &Film) {
+        for y in 0..other.height as i64 {
+            for x in 0..other.width as i64 {
+                let other_index = (y * other.width as i64 + x) as usize;
+                let index = self.index(other.x0 + x, other.y0 + y);
+                self.weighted_sums[index] += other.weighted_sums[other_index];
+                self.weights[index] += other.weights[other_index];
+                self.sample_counts[index] += other.sample_counts[other_index];
+                self.splats[index] += other.splats[other_index];
+            }
+        }
+    }
+
+    /// Returns the FrameBuffer holding the filtered color of each pixel of the Film.
+    /// Negative values, which filters with negative lobes can produce, are clamped to zero.
+    pub fn to_frame_buffer(&self) -> FrameBuffer {
+        let mut buffer = FrameBuffer::new(self.width, self.height);
+        let total_samples: u64 = self.sample_counts.iter().map(|count| *count as u64).sum();
+        let splat_scale = if total_samples > 0 {
+            (self.width * self.height) as f64 / total_samples as f64
+        } else {
+            0.0
+        };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = (y * self.width + x) as usize;
+                let weight = self.weights[index];
+                let color = if weight.abs() > 1e-12 {
+                    self.weighted_sums[index] / weight
+                } else {
+                    Color::new(0, 0, 0)
+                } + self.splats[index] * splat_scale;
+                let color = Color {
+                    r: color.r.max(0.0),
+                    g: color.g.max(0.0),
+                    b: color.b.max(0.0),
+                };
+                buffer.set_pixel(x, y, color);
+                buffer.set_sample_count(x, y, self.sample_counts[index]);
+            }
+        }
+        buffer
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        x >= self.x0
+            && y >= self.y0
+            && x < self.x0 + self.width as i64
+            && y < self.y0 + self.height as i64
+    }
+
+    fn index(&self, x: i64, y: i64) -> usize {
+        ((y - self.y0) * self.width as i64 + (x - self.x0)) as usize
+    }
+}
@@ -0,0 +1,90 @@
+use std::f64::consts::PI;
+
+/// Reconstruction filter used to combine the samples of the render into pixels.
+///
+/// Each sample contributes to all the pixels whose center is closer than the radius of the filter,
+/// weighted by the value of the filter at the offset between the sample and the pixel center.
+///
+/// Box averages the samples falling in a square around the pixel center. A radius of 0.5 matches the pixel footprint.
+/// Tent weights the samples linearly decreasing with their distance to the pixel center.
+/// Gaussian weights the samples with a gaussian of falloff alpha, shifted to reach zero at the radius.
+/// Mitchell is the Mitchell-Netravali cubic filter, B = C = 1/3 being the recommended values.
+/// Lanczos is a sinc filter windowed by a sinc stretched tau times.
+///
+/// # Example
+/// ```
+/// # use raytracer::filter::Filter;
+/// let filter = Filter::Mitchell {
+///     radius: 2.0,
+///     b: 1.0 / 3.0,
+///     c: 1.0 / 3.0,
+/// };
+/// assert!(filter.evaluate(0.0, 0.0) > 0.0);
+/// assert_eq!(filter.evaluate(2.5, 0.0), 0.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, alpha: f64 },
+    Mitchell { radius: f64, b: f64, c: f64 },
+    Lanczos { radius: f64, tau: f64 },
+}
+
+impl Filter {
+    /// Returns the radius of the filter in pixels.
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => *radius,
+        }
+    }
+
+    /// Returns the value of the filter at the offset (x,y) from the pixel center, in pixels.
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        let radius = self.radius();
+        if x > radius {
+            return 0.0;
+        }
+        match self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { .. } => radius - x,
+            Filter::Gaussian { alpha, .. } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+            Filter::Mitchell { b, c, .. } => mitchell(2.0 * x / radius, *b, *c),
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+        }
+    }
+}
+
+/// Mitchell-Netravali cubic, defined over [-2, 2].
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    if x > 1.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
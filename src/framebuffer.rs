@@ -0,0 +1,133 @@
+use image::{GrayImage, ImageBuffer, ImageFormat, ImageResult, Luma, RgbImage};
+
+use crate::output::{write_exr, write_hdr, write_pfm, OutputFormat};
+use crate::utils::Color;
+use crate::Config;
+
+/// The result of a render, holding the linear Color computed for each pixel and the number of samples it took.
+///
+/// Pixels are stored row by row, starting from the top left corner of the image.
+///
+/// # Example
+/// ```
+/// # use raytracer::framebuffer::FrameBuffer;
+/// # use raytracer::utils::Color;
+/// let mut buffer = FrameBuffer::new(2, 2);
+/// buffer.set_pixel(1, 0, Color::new(255, 0, 0));
+/// assert_eq!(buffer.get_pixel(1, 0).r, 1.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FrameBuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    sample_counts: Vec<u32>,
+}
+
+impl FrameBuffer {
+    /// Creates a new black FrameBuffer.
+    pub fn new(width: u32, height: u32) -> Self {
+        FrameBuffer {
+            width,
+            height,
+            pixels: vec![Color::new(0, 0, 0); (width * height) as usize],
+            sample_counts: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// Returns the width of the FrameBuffer in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the FrameBuffer in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the Color of the pixel at position (x,y).
+    pub fn get_pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    /// Sets the Color of the pixel at position (x,y).
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+
+    /// Returns all the pixels of the FrameBuffer, row by row.
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Returns the number of samples taken for the pixel at position (x,y).
+    pub fn get_sample_count(&self, x: u32, y: u32) -> u32 {
+        self.sample_counts[self.index(x, y)]
+    }
+
+    /// Sets the number of samples taken for the pixel at position (x,y).
+    pub fn set_sample_count(&mut self, x: u32, y: u32, sample_count: u32) {
+        let index = self.index(x, y);
+        self.sample_counts[index] = sample_count;
+    }
+
+    /// Returns the number of samples taken for all the pixels of the FrameBuffer, row by row.
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.sample_counts
+    }
+
+    /// Saves the number of samples taken for each pixel as a grayscale image at the given path.
+    /// The pixel that took the most samples is white, and the brightness of the others is proportional to their sample count.
+    pub fn save_sample_map(&self, path: &str) -> ImageResult<()> {
+        let max = self.sample_counts.iter().copied().max().unwrap_or(0).max(1);
+        let image: GrayImage = ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            Luma([(self.get_sample_count(x, y) as f64 / max as f64 * 255.0).round() as u8])
+        });
+        image.save(path)
+    }
+
+    /// Converts the FrameBuffer to an 8 bits RGB image, using the output settings of the config.
+    /// The exposure and the tone mapping operator of the config are applied to the linear colors,
+    /// which are then encoded with the transfer function of the config and quantized.
+    pub fn to_rgb_image(&self, config: &Config) -> RgbImage {
+        let exposure = 2f64.powf(config.exposure);
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            config
+                .tone_mapping
+                .apply(self.get_pixel(x, y) * exposure)
+                .convert(&config.transfer_function)
+        })
+    }
+
+    /// Saves the FrameBuffer as an image at the given path, using the output settings of the config.
+    /// The format of the image is the output_format of the config, or is deduced from the extension of the path if it is None.
+    /// High dynamic range formats store the linear colors of the FrameBuffer without any clamping, exposure or tone mapping.
+    pub fn save(&self, path: &str, config: &Config) -> ImageResult<()> {
+        match config
+            .output_format
+            .or_else(|| OutputFormat::from_path(path))
+        {
+            Some(OutputFormat::Exr) => write_exr(self, path),
+            Some(OutputFormat::Hdr) => write_hdr(self, path),
+            Some(OutputFormat::Pfm) => write_pfm(self, path),
+            Some(OutputFormat::Png) => self
+                .to_rgb_image(config)
+                .save_with_format(path, ImageFormat::Png),
+            Some(OutputFormat::Jpeg) => self
+                .to_rgb_image(config)
+                .save_with_format(path, ImageFormat::Jpeg),
+            None => self.to_rgb_image(config).save(path),
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        if x >= self.width || y >= self.height {
+            panic!(
+                "Pixel ({}, {}) is outside of the {}x{} FrameBuffer",
+                x, y, self.width, self.height
+            );
+        }
+        (y * self.width + x) as usize
+    }
+}
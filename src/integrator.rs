@@ -0,0 +1,348 @@
+use crate::bdpt::BidirectionalPathTracer;
+use crate::camera::Camera;
+use crate::ray::Ray;
+use crate::sampler::{sample_unit_sphere, Sampler};
+use crate::scene::Scene;
+use crate::shapes::collide::HitRecord;
+use crate::utils::Color;
+
+/// Light transport algorithm computing the color seen along a ray of the Camera.
+pub trait Integrator {
+    /// Returns the color seen along the ray, coming from the camera.
+    /// The random choices of the integrator are made with the Sampler.
+    /// Integrators tracing paths from the lights push the light they bring to other positions of the image in splats.
+    fn get_ray_color(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        ray: Ray,
+        splats: &mut Vec<Splat>,
+    ) -> Color;
+}
+
+/// Light brought by a sample to a position of the image, in pixels.
+pub struct Splat {
+    pub x: f64,
+    pub y: f64,
+    pub color: Color,
+}
+
+/// The different Integrators that can be used to render a Scene, from the most accurate to the fastest.
+///
+/// Bidirectional traces paths from both the camera and the emissive shapes and connects them, see BidirectionalPathTracer.
+/// It is slower per sample but converges much faster on caustics and on scenes lit indirectly.
+/// NeePathTracing is a path tracer sampling the lights directly at each bounce (next event estimation),
+/// combined with the sampling of the materials through multiple importance sampling.
+/// PathTracing is a path tracer that only follows the directions sampled by the materials and finds the emissive shapes by chance.
+/// It is much noisier with small lights and is mostly useful as a reference.
+/// PhotonMapping shoots the given number of photons from the emissive shapes before rendering and stores them in the Scene.
+/// The rays follow the mirror and glass reflections, and the light bounced at least once is estimated at the first other surface
+/// from the photons closer than radius, the direct lighting being sampled as usual. It renders clean caustics under glass shapes,
+/// but the skybox only lights the surfaces directly and too large a radius blurs the indirect lighting.
+/// Whitted follows the mirror and glass reflections and only computes the direct lighting on the other surfaces, for fast previews.
+/// AmbientOcclusion renders the fraction of the hemisphere around the first hit that isn't occluded by the shapes closer than distance.
+///
+/// Point, spot and directional lights can't be hit by the rays, they are always sampled directly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegratorKind {
+    Bidirectional,
+    NeePathTracing,
+    PathTracing,
+    PhotonMapping { photons: u32, radius: f64 },
+    Whitted,
+    AmbientOcclusion { distance: f64 },
+}
+
+impl IntegratorKind {
+    /// Creates a new Integrator of this kind.
+    pub fn create(&self) -> Box<dyn Integrator + Send + Sync> {
+        match self {
+            IntegratorKind::Bidirectional => Box::new(BidirectionalPathTracer),
+            IntegratorKind::NeePathTracing => Box::new(PathTracer {
+                next_event_estimation: true,
+            }),
+            IntegratorKind::PathTracing => Box::new(PathTracer {
+                next_event_estimation: false,
+            }),
+            IntegratorKind::PhotonMapping { radius, .. } => {
+                Box::new(PhotonMapper { radius: *radius })
+            }
+            IntegratorKind::Whitted => Box::new(WhittedIntegrator),
+            IntegratorKind::AmbientOcclusion { distance } => Box::new(AmbientOcclusionIntegrator {
+                distance: *distance,
+            }),
+        }
+    }
+}
+
+struct PathTracer {
+    next_event_estimation: bool,
+}
+
+impl Integrator for PathTracer {
+    /// The path is followed iteratively, keeping the throughput of the light along it.
+    /// When the lights were sampled directly at the previous hit, the light emitted by the hit shape is weighted with multiple importance sampling.
+    fn get_ray_color(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        ray: Ray,
+        _: &mut Vec<Splat>,
+    ) -> Color {
+        let config = scene.get_config();
+        let mut color = Color::new(0, 0, 0);
+        let mut throughput = Color::new(255, 255, 255);
+        let mut ray = ray;
+        // Density with which the ray was sampled by the material of the previous hit, if the lights were also sampled directly there
+        let mut bsdf_pdf: Option<f64> = None;
+
+        for bounce in 0..config.max_ray_bounce {
+            // Get the intersection that is the closest to the camera, or render the skybox
+            let hit = match scene.get_intersection(camera, &ray, bounce == 0) {
+                Some(hit) => hit,
+                None => {
+                    color += throughput * scene.get_skybox_color();
+                    break;
+                }
+            };
+            // The light is absorbed on its way through the inside of the object
+            throughput *= hit.material.transmittance(&ray, &hit);
+
+            let emited = match bsdf_pdf {
+                Some(bsdf_pdf) if hit.material.is_emissive() => {
+                    let light_pdf = scene.light_pdf(&ray.origin, &ray.direction);
+                    hit.material.emit() * power_heuristic(bsdf_pdf, light_pdf)
+                }
+                _ => hit.material.emit(),
+            };
+
+            let bouncing_ray = match hit.material.scatter(&ray, &hit, sampler) {
+                Some(bouncing_ray) => bouncing_ray,
+                None => {
+                    color += throughput * emited;
+                    break;
+                }
+            };
+
+            // Sample the lights directly on non specular surfaces (next event estimation)
+            let sample_lights =
+                self.next_event_estimation && !hit.material.is_specular() && scene.has_lights();
+            if sample_lights {
+                color += throughput * scene.sample_light(&ray, &hit, sampler, true);
+            }
+            if !hit.material.is_specular() {
+                color += throughput * scene.sample_punctual_lights(&ray, &hit);
+            }
+
+            let pdf = hit.material.pdf(&ray, &hit, &bouncing_ray.direction);
+            if pdf > 0.0 {
+                throughput *= hit.material.eval(&ray, &hit, &bouncing_ray.direction) / pdf;
+                bsdf_pdf = Some(pdf).filter(|_| sample_lights);
+            } else {
+                throughput *= hit.material.get_attenuation(&hit);
+                bsdf_pdf = None;
+            }
+            if throughput.is_black() {
+                break;
+            }
+
+            // Russian roulette: stop the paths carrying little light, and compensate for it on the surviving ones
+            if let Some(depth) = config.russian_roulette_depth {
+                if bounce + 1 >= depth {
+                    let survival = throughput.r.max(throughput.g).max(throughput.b).min(0.95);
+                    if sampler.next_1d() >= survival {
+                        break;
+                    }
+                    throughput /= survival;
+                }
+            }
+
+            ray = bouncing_ray;
+        }
+
+        color
+    }
+}
+
+struct WhittedIntegrator;
+
+impl Integrator for WhittedIntegrator {
+    /// Specular surfaces are followed until a surface which isn't specular is hit.
+    /// Its color is its emission plus the light directly received from the lights and the skybox.
+    fn get_ray_color(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        ray: Ray,
+        _: &mut Vec<Splat>,
+    ) -> Color {
+        let mut throughput = Color::new(255, 255, 255);
+        let mut ray = ray;
+
+        for bounce in 0..scene.get_config().max_ray_bounce {
+            let hit = match scene.get_intersection(camera, &ray, bounce == 0) {
+                Some(hit) => hit,
+                None => return throughput * scene.get_skybox_color(),
+            };
+            throughput *= hit.material.transmittance(&ray, &hit);
+            let bouncing_ray = match hit.material.scatter(&ray, &hit, sampler) {
+                Some(bouncing_ray) => bouncing_ray,
+                None => return throughput * hit.material.emit(),
+            };
+
+            if hit.material.is_specular() {
+                throughput *= hit.material.get_attenuation(&hit);
+                ray = bouncing_ray;
+                continue;
+            }
+            return throughput
+                * direct_lighting(scene, camera, sampler, &ray, &hit, &bouncing_ray, true);
+        }
+
+        Color::new(0, 0, 0)
+    }
+}
+
+struct PhotonMapper {
+    radius: f64,
+}
+
+impl Integrator for PhotonMapper {
+    /// Specular surfaces are followed until a surface which isn't specular is hit.
+    /// Its color is its direct lighting plus the indirect light estimated from the photons around it.
+    fn get_ray_color(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        ray: Ray,
+        _: &mut Vec<Splat>,
+    ) -> Color {
+        let mut color = Color::new(0, 0, 0);
+        let mut throughput = Color::new(255, 255, 255);
+        let mut ray = ray;
+        // The emissive shapes are sampled directly at the points of the media, their light must not be counted twice
+        let mut count_emission = true;
+
+        for bounce in 0..scene.get_config().max_ray_bounce {
+            let hit = match scene.get_intersection(camera, &ray, bounce == 0) {
+                Some(hit) => hit,
+                None => return color + throughput * scene.get_skybox_color(),
+            };
+            throughput *= hit.material.transmittance(&ray, &hit);
+            let bouncing_ray = match hit.material.scatter(&ray, &hit, sampler) {
+                Some(bouncing_ray) => bouncing_ray,
+                None if count_emission => return color + throughput * hit.material.emit(),
+                None => return color,
+            };
+
+            if hit.material.is_specular() {
+                throughput *= hit.material.get_attenuation(&hit);
+                ray = bouncing_ray;
+                count_emission = true;
+                continue;
+            }
+            // There are no photons in the media: the path goes on through them, lit directly at each scattering point
+            if hit.material.is_volumetric() {
+                color += throughput * scene.sample_punctual_lights(&ray, &hit);
+                if scene.has_lights() {
+                    color += throughput * scene.sample_light(&ray, &hit, sampler, false);
+                }
+                throughput *= hit.material.get_attenuation(&hit);
+                if throughput.is_black() {
+                    return color;
+                }
+                ray = bouncing_ray;
+                count_emission = false;
+                continue;
+            }
+            let direct = direct_lighting(
+                scene,
+                camera,
+                sampler,
+                &ray,
+                &hit,
+                &bouncing_ray,
+                count_emission,
+            );
+            return color + throughput * (direct + scene.gather_photons(&ray, &hit, self.radius));
+        }
+
+        color
+    }
+}
+
+/// Returns the light emitted by the hit if include_emission is true, plus the light it directly receives from the lights,
+/// and from the skybox if the bouncing ray sampled by its material escapes the Scene.
+fn direct_lighting(
+    scene: &Scene,
+    camera: &Camera,
+    sampler: &mut dyn Sampler,
+    ray: &Ray,
+    hit: &HitRecord,
+    bouncing_ray: &Ray,
+    include_emission: bool,
+) -> Color {
+    let mut direct = scene.sample_punctual_lights(ray, hit);
+    if include_emission {
+        direct += hit.material.emit();
+    }
+    if scene.has_lights() {
+        direct += scene.sample_light(ray, hit, sampler, false);
+    }
+
+    if scene
+        .get_intersection(camera, bouncing_ray, false)
+        .is_none()
+    {
+        let pdf = hit.material.pdf(ray, hit, &bouncing_ray.direction);
+        let weight = if pdf > 0.0 {
+            hit.material.eval(ray, hit, &bouncing_ray.direction) / pdf
+        } else {
+            hit.material.get_attenuation(hit)
+        };
+        direct += weight * scene.get_skybox_color();
+    }
+    direct
+}
+
+struct AmbientOcclusionIntegrator {
+    distance: f64,
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    /// The directions are sampled with a cosine distribution around the normal,
+    /// so the color is white if the sampled direction is not occluded and black otherwise.
+    fn get_ray_color(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        ray: Ray,
+        _: &mut Vec<Splat>,
+    ) -> Color {
+        let white = Color::new(255, 255, 255);
+        let hit = match scene.get_intersection(camera, &ray, true) {
+            Some(hit) => hit,
+            None => return white,
+        };
+        let direction = (hit.normal + sample_unit_sphere(sampler.next_2d())).normalize();
+        let occlusion_ray = Ray::new(hit.point, direction);
+        match scene.get_intersection(camera, &occlusion_ray, false) {
+            Some(occluder) if occluder.t < self.distance => Color::new(0, 0, 0),
+            _ => white,
+        }
+    }
+}
+
+/// Weight of a sample taken with the density pdf, combined with another strategy of density other_pdf.
+pub(crate) fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
@@ -1,93 +1,475 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
+use std::thread;
 
+use adaptive::{AdaptiveSampling, PixelEstimator};
 use camera::Camera;
-use image::ImageBuffer;
+use color_management::TransferFunction;
+use film::Film;
+use filter::Filter;
+use framebuffer::FrameBuffer;
 use indicatif::{HumanDuration, ProgressBar};
+use integrator::{IntegratorKind, Splat};
+use output::OutputFormat;
+use sampler::{Sampler, SamplerKind};
 use scene::Scene;
+use tile::{generate_tiles, TileOrder};
+use tone_mapping::ToneMapping;
 
+pub mod adaptive;
+mod bdpt;
 mod bvh;
 pub mod camera;
+pub mod color_management;
+mod film;
+pub mod filter;
+pub mod framebuffer;
+pub mod integrator;
+pub mod light;
 pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod normal_map;
+pub mod output;
+mod photon_map;
 mod ray;
+pub mod sampler;
 pub mod scene;
 pub mod shapes;
+pub mod texture;
+pub mod tile;
+pub mod tone_mapping;
 pub mod utils;
+pub mod voxel_grid;
 
 /// Configuration of the output image
 ///
-/// output_path must be a valid path with the name of the file to create. The extension of the file should be either png or jpeg.
+/// output_path must be a valid path with the name of the file to create.
+/// output_format is the format of the saved image. If it is None, the format is deduced from the extension of output_path:
+/// png and jpeg produce 8 bits images, while exr, hdr and pfm keep the full dynamic range of the render as 32 bits floats.
 /// anti_aliasing holds the value of the square root of the number of rays thrown per pixel.
 /// For example, if anti_aliasing = Some(5), 25 rays per pixel will be thrown. Increasing this value will result in less performance.
+/// filter is the reconstruction filter used to combine the samples into pixels. Each sample contributes to all the pixels within the radius of the filter.
+/// sampler is the kind of Sampler used to choose the position of the rays in the pixels, on the camera len and their bounce directions.
+/// seed is the seed of all the random choices of the renderer. Two renders of the same scene with the same seed produce identical images.
+/// integrator is the light transport algorithm used to compute the color of the rays, see IntegratorKind.
 /// max_ray_bounce set the maximum time a ray should bounce on objects before considering it was fully absorbed.
-/// gamma_correction is the gamma correction that should be applied to the image.
+/// russian_roulette_depth is the number of bounces after which the rays are randomly stopped with a probability depending on the light they still carry.
+/// The light of the surviving rays is increased accordingly, so that the image stays the same with less work. None disables it.
+/// transfer_function encodes the linear colors of the render for 8 bits images. Use TransferFunction::Srgb unless the image is meant to be read by a tool expecting another encoding.
+/// exposure is the exposure adjustment of the image in stops, each stop doubling the brightness of the image.
+/// tone_mapping is the operator used to map the high dynamic range colors of the render to an 8 bits image.
+/// tile_size is the size in pixels of the square tiles the image is split into. Each tile is rendered by a single thread.
+/// tile_order is the order in which the tiles are rendered.
+/// progressive_passes enables the progressive rendering in the render function when it is not None.
+/// The image is then refined in the given number of passes, doubling the number of samples per pixel at each pass, and saved after each of them.
+/// There can be at most 31 passes.
+/// anti_aliasing is ignored in progressive mode.
+/// adaptive_sampling enables the adaptive sampling when it is not None, in which case anti_aliasing is ignored.
+/// The number of samples of each pixel then depends on its noise level, see the AdaptiveSampling struct. It is not used in progressive mode.
 ///
 /// # Example
 /// ```
+/// # use raytracer::Config;
+/// # use raytracer::sampler::SamplerKind;
+/// # use raytracer::color_management::TransferFunction;
+/// # use raytracer::filter::Filter;
+/// # use raytracer::integrator::IntegratorKind;
+/// # use raytracer::tile::TileOrder;
+/// # use raytracer::tone_mapping::ToneMapping;
 /// let config = Config {
 ///     width: 640,
 ///     height: 400,
 ///     output_path: String::from("images/test.png"),
+///     output_format: None,
 ///     anti_aliasing: Some(10),
+///     filter: Filter::Gaussian { radius: 1.5, alpha: 2.0 },
+///     sampler: SamplerKind::Sobol,
+///     seed: 42,
+///     integrator: IntegratorKind::NeePathTracing,
 ///     max_ray_bounce: 20,
-///     gamma_correction: 1.0,
+///     russian_roulette_depth: Some(5),
+///     transfer_function: TransferFunction::Srgb,
+///     exposure: 0.0,
+///     tone_mapping: ToneMapping::Aces,
+///     tile_size: 32,
+///     tile_order: TileOrder::Spiral,
+///     progressive_passes: None,
+///     adaptive_sampling: None,
 /// };
 /// ```
 pub struct Config {
     pub width: u32,
     pub height: u32,
     pub output_path: String,
+    pub output_format: Option<OutputFormat>,
     pub anti_aliasing: Option<u32>,
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    pub seed: u64,
+    pub integrator: IntegratorKind,
     pub max_ray_bounce: u32,
-    pub gamma_correction: f64,
+    pub russian_roulette_depth: Option<u32>,
+    pub transfer_function: TransferFunction,
+    pub exposure: f64,
+    pub tone_mapping: ToneMapping,
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    pub progressive_passes: Option<u32>,
+    pub adaptive_sampling: Option<AdaptiveSampling>,
 }
 
-/// Renders the Scene scene from the Camera camera.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            width: 640,
+            height: 400,
+            output_path: String::from("images/test.png"),
+            output_format: None,
+            anti_aliasing: Some(10),
+            filter: Filter::Box { radius: 0.5 },
+            sampler: SamplerKind::Stratified,
+            seed: 0,
+            integrator: IntegratorKind::NeePathTracing,
+            max_ray_bounce: 20,
+            russian_roulette_depth: Some(5),
+            transfer_function: TransferFunction::Srgb,
+            exposure: 0.0,
+            tone_mapping: ToneMapping::None,
+            tile_size: 32,
+            tile_order: TileOrder::Scanline,
+            progressive_passes: None,
+            adaptive_sampling: None,
+        }
+    }
+}
+
+/// Renders the Scene scene from the Camera camera and returns the resulting FrameBuffer.
 /// Will display a progress bar to keep track of the rendering process
-/// The resulting render will be saved in an image whose path is defined in the config object of the Scene.
-pub fn render(scene: Scene, camera: Camera) {
+/// The image is split in tiles that are rendered in parallel, using as many threads as there are cores on the machine.
+/// Each pixel of the returned FrameBuffer holds the linear Color computed for it, before any output transformation.
+///
+/// # Example
+/// ```
+/// # use raytracer::camera::Camera;
+/// # use raytracer::filter::Filter;
+/// # use raytracer::render_to_buffer;
+/// # use raytracer::scene::SceneBuilder;
+/// # use raytracer::shapes::sphere::Sphere;
+/// # use raytracer::utils::Vec3;
+/// # use raytracer::Config;
+/// let config = Config {
+///     width: 48,
+///     height: 32,
+///     anti_aliasing: Some(2),
+///     tile_size: 8,
+///     filter: Filter::Gaussian { radius: 1.5, alpha: 2.0 },
+///     ..Config::default()
+/// };
+/// let mut scene_builder = SceneBuilder::new(config);
+/// scene_builder.add_shape(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5));
+/// let scene = scene_builder.to_scene();
+/// let camera = Camera::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 1.5);
+///
+/// // The same Scene rendered twice gives the same image, whatever the scheduling of the threads
+/// let first = render_to_buffer(&scene, &camera);
+/// let second = render_to_buffer(&scene, &camera);
+/// let bits = |buffer: &raytracer::framebuffer::FrameBuffer| {
+///     buffer.pixels().iter().map(|c| [c.r.to_bits(), c.g.to_bits(), c.b.to_bits()]).collect::<Vec<_>>()
+/// };
+/// assert_eq!(bits(&first), bits(&second));
+/// ```
+pub fn render_to_buffer(scene: &Scene, camera: &Camera) -> FrameBuffer {
     println!("Rendering scene...");
-    let bar = ProgressBar::new(scene.get_config().width as u64 * scene.get_config().height as u64);
-    let bar = Arc::new(bar);
+    let config = scene.get_config();
+    let bar = ProgressBar::new(config.width as u64 * config.height as u64);
     bar.set_draw_rate(10);
 
-    let buffer: ImageBuffer<image::Rgb<u8>, _> =
-        ImageBuffer::new(scene.get_config().width, scene.get_config().height);
-    let buffer = Arc::new(Mutex::new(buffer));
-    let thread_pool = threadpool::ThreadPool::new(100);
-
-    let gamma_correction = scene.get_config().gamma_correction;
-    let camera = Arc::new(camera);
-    let scene = Arc::new(scene);
-
-    let width = buffer.lock().unwrap().width();
-    let height = buffer.lock().unwrap().height();
-
-    for x in 0..width {
-        for y in 0..height {
-            let camera_clone = camera.clone();
-            let buffer_clone = buffer.clone();
-            let scene_clone = scene.clone();
-            let bar_clone = bar.clone();
-
-            thread_pool.execute(move || {
-                let color = scene_clone
-                    .get_pixel_color(&camera_clone, x as u32, y as u32)
-                    .convert(gamma_correction);
-                *buffer_clone.lock().unwrap().get_pixel_mut(x, y) = color;
-                bar_clone.inc(1);
-            });
+    let (sampling, samples_per_pixel) = match (&config.adaptive_sampling, config.anti_aliasing) {
+        (Some(adaptive_sampling), _) => (
+            PixelSampling::Adaptive(adaptive_sampling),
+            adaptive_sampling.max_samples,
+        ),
+        (None, Some(n_samples_root)) => {
+            let n_samples = n_samples_root * n_samples_root;
+            (
+                PixelSampling::Fixed {
+                    first_sample: 0,
+                    n_samples,
+                },
+                n_samples,
+            )
         }
-    }
-    thread_pool.join();
+        (None, None) => (PixelSampling::Center, 1),
+    };
+    let film = render_tiles(scene, camera, &bar, &sampling, samples_per_pixel);
 
     bar.finish();
-    println!("Took: {}", HumanDuration(bar.elapsed()).to_string());
+    println!("Took: {}", HumanDuration(bar.elapsed()));
+    film.to_frame_buffer()
+}
 
-    println!("Saving image...");
+/// Progressively renders the Scene scene from the Camera camera and returns the resulting FrameBuffer.
+///
+/// The whole image is refined pass after pass, the pass number n (starting from 0) taking 2^n new samples per pixel.
+/// After each pass, the callback receives the number of the pass and the image accumulated so far.
+/// The rendering stops after the given number of passes, or as soon as the callback returns false.
+/// There can be at most 31 passes, the last one then taking 2^30 samples per pixel.
+///
+/// # Example
+/// ```no_run
+/// # use raytracer::render_progressive;
+/// # fn example(scene: raytracer::scene::Scene, camera: raytracer::camera::Camera) {
+/// let buffer = render_progressive(&scene, &camera, 8, |pass, image| {
+///     image.save("images/preview.png", scene.get_config()).unwrap();
+///     pass < 5
+/// });
+/// # }
+/// ```
+pub fn render_progressive<F>(
+    scene: &Scene,
+    camera: &Camera,
+    passes: u32,
+    mut callback: F,
+) -> FrameBuffer
+where
+    F: FnMut(u32, &FrameBuffer) -> bool,
+{
+    if passes > 31 {
+        panic!("A progressive rendering can't have more than 31 passes");
+    }
+    println!("Rendering scene progressively...");
+    let config = scene.get_config();
+    let mut accumulated = Film::new(config.width, config.height, config.filter);
+    let mut n_samples = 0;
+    let total_samples = (1u32 << passes) - 1;
+    let mut buffer = accumulated.to_frame_buffer();
+
+    for pass in 0..passes {
+        let pass_samples = 1 << pass;
+        let bar = ProgressBar::new(config.width as u64 * config.height as u64);
+        bar.set_draw_rate(10);
+
+        let sampling = PixelSampling::Fixed {
+            first_sample: n_samples,
+            n_samples: pass_samples,
+        };
+        accumulated.merge(&render_tiles(scene, camera, &bar, &sampling, total_samples));
+        n_samples += pass_samples;
+        buffer = accumulated.to_frame_buffer();
+
+        bar.finish();
+        println!(
+            "Pass {} ({} samples per pixel) took: {}",
+            pass + 1,
+            n_samples,
+            HumanDuration(bar.elapsed())
+        );
+        if !callback(pass, &buffer) {
+            break;
+        }
+    }
     buffer
-        .lock()
-        .unwrap()
-        .save(&scene.get_config().output_path)
-        .unwrap();
+}
+
+/// Renders the Scene scene from the Camera camera.
+/// Will display a progress bar to keep track of the rendering process
+/// The resulting render will be saved in an image whose path is defined in the config object of the Scene.
+/// If progressive_passes is set in the config, the image is saved after every pass of the progressive rendering.
+///
+/// # Panics
+/// Panics if the tone mapping operator of the config has invalid parameters.
+pub fn render(scene: Scene, camera: Camera) {
+    let config = scene.get_config();
+    // Fail before rendering rather than when saving the image
+    config.tone_mapping.check();
+    let buffer = match config.progressive_passes {
+        // The image is saved after every pass, so it only needs saving here if there was no pass
+        Some(passes) => {
+            let buffer = render_progressive(&scene, &camera, passes, |_, image| {
+                println!("Saving image...");
+                image.save(&config.output_path, config).unwrap();
+                true
+            });
+            if passes == 0 {
+                buffer.save(&config.output_path, config).unwrap();
+            }
+            buffer
+        }
+        None => {
+            let buffer = render_to_buffer(&scene, &camera);
+            println!("Saving image...");
+            buffer.save(&config.output_path, config).unwrap();
+            buffer
+        }
+    };
+
+    if let Some(AdaptiveSampling {
+        sample_map_path: Some(path),
+        ..
+    }) = &config.adaptive_sampling
+    {
+        buffer.save_sample_map(path).unwrap();
+    }
     println!("Done");
 }
+
+/// How the samples of each pixel are taken.
+enum PixelSampling<'a> {
+    /// A single sample through the center of the pixel.
+    Center,
+    /// n_samples samples, starting from the sample number first_sample.
+    Fixed { first_sample: u32, n_samples: u32 },
+    /// As many samples as needed for the pixel to converge.
+    Adaptive(&'a AdaptiveSampling),
+}
+
+/// The film of a rendered tile, and the light it splatted to the rest of the image.
+type RenderedTile = (Film, Vec<Splat>);
+
+/// Merges the rendered tiles and their splats into the film of the image in the order of the tiles,
+/// as soon as all the previous tiles are merged.
+struct TileMerger {
+    film: Film,
+    /// Rendered tiles waiting for the previous ones to be merged
+    pending: Vec<Option<RenderedTile>>,
+    next_to_merge: usize,
+}
+
+impl TileMerger {
+    fn new(film: Film, n_tiles: usize) -> Self {
+        TileMerger {
+            film,
+            pending: (0..n_tiles).map(|_| None).collect(),
+            next_to_merge: 0,
+        }
+    }
+
+    /// Adds the rendered tile of the given index, then merges the tiles that are ready.
+    fn add(&mut self, index: usize, tile_film: Film, splats: Vec<Splat>) {
+        self.pending[index] = Some((tile_film, splats));
+        while let Some((tile_film, splats)) = self
+            .pending
+            .get_mut(self.next_to_merge)
+            .and_then(Option::take)
+        {
+            self.film.merge(&tile_film);
+            for splat in splats {
+                self.film.add_splat(splat.x, splat.y, splat.color);
+            }
+            self.next_to_merge += 1;
+        }
+    }
+
+    fn into_film(self) -> Film {
+        self.film
+    }
+}
+
+/// Renders every pixel of the image, splitting the work in tiles rendered in parallel.
+/// Each thread uses its own Sampler, created for pixels taking at most samples_per_pixel samples.
+fn render_tiles(
+    scene: &Scene,
+    camera: &Camera,
+    bar: &ProgressBar,
+    sampling: &PixelSampling,
+    samples_per_pixel: u32,
+) -> Film {
+    let config = scene.get_config();
+    let (width, height) = (config.width, config.height);
+    let tiles = generate_tiles(width, height, config.tile_size, config.tile_order);
+
+    // The tile films overlap by the radius of the filter, and the splats land anywhere in the image: both are added
+    // in the order of the tiles, so that the sums of the pixels don't depend on the order in which the threads finish their tiles
+    let merger = Mutex::new(TileMerger::new(
+        Film::new(width, height, config.filter),
+        tiles.len(),
+    ));
+    let tiles = Mutex::new(tiles.into_iter().enumerate());
+    let n_workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    thread::scope(|s| {
+        for _ in 0..n_workers {
+            s.spawn(|| {
+                let mut sampler = config.sampler.create(samples_per_pixel, config.seed);
+                loop {
+                    let (index, tile) = match tiles.lock().unwrap().next() {
+                        Some(tile) => tile,
+                        None => break,
+                    };
+
+                    // Render the tile in a local film, merged into the image once the previous tiles are
+                    let mut tile_film = Film::for_tile(&tile, width, height, config.filter);
+                    let mut splats = vec![];
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            render_pixel(
+                                scene,
+                                camera,
+                                sampler.as_mut(),
+                                &mut tile_film,
+                                x,
+                                y,
+                                sampling,
+                                &mut splats,
+                            );
+                        }
+                    }
+
+                    merger.lock().unwrap().add(index, tile_film, splats);
+                    bar.inc((tile.width * tile.height) as u64);
+                }
+            });
+        }
+    });
+
+    merger.into_inner().unwrap().into_film()
+}
+
+/// Takes the samples of the pixel at position (x,y) and adds them to the film.
+/// The light splatted to other pixels is pushed in splats.
+#[allow(clippy::too_many_arguments)]
+fn render_pixel(
+    scene: &Scene,
+    camera: &Camera,
+    sampler: &mut dyn Sampler,
+    film: &mut Film,
+    x: u32,
+    y: u32,
+    sampling: &PixelSampling,
+    splats: &mut Vec<Splat>,
+) {
+    match sampling {
+        PixelSampling::Center => {
+            sampler.start_sample(x, y, 0);
+            let (sample_x, sample_y) = (x as f64 + 0.5, y as f64 + 0.5);
+            let color = scene.get_image_point_color(camera, sampler, sample_x, sample_y, splats);
+            film.add_sample(sample_x, sample_y, color);
+        }
+        PixelSampling::Fixed {
+            first_sample,
+            n_samples,
+        } => {
+            for sample_index in *first_sample..first_sample + n_samples {
+                let (sample_x, sample_y, color) =
+                    scene.get_pixel_sample(camera, sampler, x, y, sample_index, splats);
+                film.add_sample(sample_x, sample_y, color);
+            }
+        }
+        PixelSampling::Adaptive(adaptive_sampling) => {
+            let mut estimator = PixelEstimator::new();
+            while estimator.count() < adaptive_sampling.max_samples {
+                let (sample_x, sample_y, color) =
+                    scene.get_pixel_sample(camera, sampler, x, y, estimator.count(), splats);
+                film.add_sample(sample_x, sample_y, color);
+                estimator.add(color);
+                if estimator.count() >= adaptive_sampling.min_samples
+                    && estimator.has_converged(adaptive_sampling.threshold)
+                {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::utils::{dot, Color, Vec3};
+
+/// Implement this trait for the lights that are not shapes of the Scene.
+/// Such lights are points or directions, they can't be hit by the rays and are sampled directly with shadow rays.
+pub trait Light {
+    /// Returns the light arriving at the point, if any.
+    fn illuminate(&self, point: &Vec3) -> Option<LightSample>;
+}
+
+/// Light arriving at a point from a Light.
+pub struct LightSample {
+    /// Normalized direction from the point to the light.
+    pub direction: Vec3,
+    /// Distance from the point to the light, infinite for directional lights.
+    pub distance: f64,
+    /// Light arriving at the point, including the falloff of the light.
+    pub radiance: Color,
+}
+
+/// A light emitting in all directions from a point, with an inverse-square falloff.
+///
+/// # Example
+/// ```
+/// # use raytracer::light::{Light, PointLight};
+/// # use raytracer::utils::{Color, Vec3};
+/// let light = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Color::new(255, 255, 255), 4.0);
+///
+/// let sample = light.illuminate(&Vec3::new(0.0, 0.0, 0.0)).unwrap();
+/// assert_eq!(sample.distance, 2.0);
+/// assert_eq!(sample.radiance.r, 1.0);
+/// ```
+pub struct PointLight {
+    position: Vec3,
+    color: Color,
+    intensity: f64,
+}
+
+impl PointLight {
+    /// Creates a new PointLight.
+    pub fn new(position: Vec3, color: Color, intensity: f64) -> Self {
+        PointLight {
+            position,
+            color,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn illuminate(&self, point: &Vec3) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance_squared = to_light.len_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light.normalize(),
+            distance: distance_squared.sqrt(),
+            radiance: self.color * (self.intensity / distance_squared),
+        })
+    }
+}
+
+/// A point light emitting in a cone around its direction, with an inverse-square falloff.
+///
+/// The light is full inside the falloff angle and fades smoothly to zero at the cone angle.
+/// Both angles are measured from the direction of the light, in degrees. By default they are 30 and 25 degrees.
+///
+/// # Example
+/// ```
+/// # use raytracer::light::SpotLight;
+/// # use raytracer::utils::{Color, Vec3};
+/// let light = SpotLight::new(
+///     Vec3::new(0.0, 5.0, 0.0),
+///     Vec3::new(0.0, -1.0, 0.0),
+///     Color::new(255, 240, 200),
+///     50.0,
+/// )
+/// .set_cone_angle(20.0)
+/// .set_falloff_angle(10.0);
+/// ```
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    color: Color,
+    intensity: f64,
+    cos_cone_angle: f64,
+    cos_falloff_angle: f64,
+}
+
+impl SpotLight {
+    /// Creates a new SpotLight at position, pointing toward direction.
+    pub fn new(position: Vec3, direction: Vec3, color: Color, intensity: f64) -> Self {
+        SpotLight {
+            position,
+            direction: direction.normalize(),
+            color,
+            intensity,
+            cos_cone_angle: 30f64.to_radians().cos(),
+            cos_falloff_angle: 25f64.to_radians().cos(),
+        }
+    }
+
+    /// Sets the angle, in degrees, beyond which the SpotLight doesn't emit any light.
+    pub fn set_cone_angle(mut self, cone_angle: f64) -> Self {
+        self.cos_cone_angle = cone_angle.to_radians().cos();
+        self
+    }
+
+    /// Sets the angle, in degrees, beyond which the light of the SpotLight starts fading.
+    /// It should be smaller than the cone angle, a larger one is clamped to the cone angle.
+    pub fn set_falloff_angle(mut self, falloff_angle: f64) -> Self {
+        self.cos_falloff_angle = falloff_angle.to_radians().cos();
+        self
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta <= self.cos_cone_angle {
+            return 0.0;
+        }
+        // The light fades inside the cone, even if the falloff angle was set beyond it
+        let cos_falloff_angle = self.cos_falloff_angle.max(self.cos_cone_angle);
+        if cos_theta >= cos_falloff_angle {
+            return 1.0;
+        }
+        let x = (cos_theta - self.cos_cone_angle) / (cos_falloff_angle - self.cos_cone_angle);
+        x * x * (3.0 - 2.0 * x)
+    }
+}
+
+impl Light for SpotLight {
+    fn illuminate(&self, point: &Vec3) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance_squared = to_light.len_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        let direction = to_light.normalize();
+        let falloff = self.falloff(dot(&-direction, &self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance: distance_squared.sqrt(),
+            radiance: self.color * (self.intensity * falloff / distance_squared),
+        })
+    }
+}
+
+/// A light coming from infinitely far away in a single direction, like the sun.
+///
+/// # Example
+/// ```
+/// # use raytracer::light::DirectionalLight;
+/// # use raytracer::utils::{Color, Vec3};
+/// let sun = DirectionalLight::new(Vec3::new(-1.0, -2.0, -0.5), Color::new(255, 250, 235), 3.0);
+/// ```
+pub struct DirectionalLight {
+    direction: Vec3,
+    color: Color,
+    intensity: f64,
+}
+
+impl DirectionalLight {
+    /// Creates a new DirectionalLight whose light travels along direction.
+    pub fn new(direction: Vec3, color: Color, intensity: f64) -> Self {
+        DirectionalLight {
+            direction: direction.normalize(),
+            color,
+            intensity,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn illuminate(&self, _: &Vec3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            radiance: self.color * self.intensity,
+        })
+    }
+}
@@ -1,112 +1,225 @@
-use rand::{thread_rng, Rng};
-use rand_distr::{num_traits::Pow, Distribution, UnitSphere};
+use std::f64::consts::PI;
 
 use crate::{
     ray::Ray,
+    sampler::{sample_unit_sphere, Sampler},
     shapes::collide::HitRecord,
+    texture::Texture,
     utils::{dot, Color, Vec3},
 };
 
+type BoxTexture = Box<dyn Texture + Send + Sync>;
+
 pub trait Material {
     /// Returns a ray that was scattered byt the material, based on the incident ray and the informations about the hit with the object.
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<Ray>;
+    /// The random choices of the material are made with the Sampler.
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<Ray>;
 
     /// Returns the color emitted by the Material
     fn emit(&self) -> Color {
         Color::new(0, 0, 0)
     }
 
-    /// Returns the attenuation that the scattered ray went through. This is the albedo color of the material.
-    fn get_attenuation(&self) -> Color;
+    /// Returns true if the Material emits light. Shapes with an emissive Material are sampled directly as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Returns the value of the BSDF for the incident ray scattered in the direction, multiplied by the cosine of the direction with the normal.
+    /// Materials that don't implement it are never lit by the direct sampling of the lights.
+    fn eval(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Vec3) -> Color {
+        Color::new(0, 0, 0)
+    }
+
+    /// Returns the probability density, in solid angle, that scatter returns a ray in the direction.
+    /// A density of zero means that the direction can't be evaluated, the scattered ray is then weighted by the attenuation.
+    fn pdf(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Returns true if the Material only scatters in a discrete set of directions, like a mirror or glass.
+    /// The lights can't be sampled directly from specular surfaces.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// Returns the fraction of the light that reached the hit, for rays travelling inside an object of this Material.
+    /// It is white for the Materials that don't absorb the light inside them.
+    fn transmittance(&self, _ray: &Ray, _hit_record: &HitRecord) -> Color {
+        Color::new(255, 255, 255)
+    }
+
+    /// Returns true if the Material is the phase function of a participating medium, scattering light at points inside a volume.
+    /// Such hits have no surface: their normal is only the opposite of the ray direction.
+    fn is_volumetric(&self) -> bool {
+        false
+    }
+
+    /// Returns the attenuation that the scattered ray went through. This is the albedo color of the material at the hit.
+    fn get_attenuation(&self, hit_record: &HitRecord) -> Color;
 }
 
 /// A pure diffuse Material.
+///
+/// Its color is a Texture, either a constant Color or a pattern applied on the shape.
 pub struct Diffuse {
-    color: Color,
+    texture: BoxTexture,
 }
 
 impl Material for Diffuse {
-    fn scatter(&self, _: &Ray, hit_record: &HitRecord) -> Option<Ray> {
-        let r: [f64; 3] = UnitSphere.sample(&mut rand::thread_rng());
-        let target = hit_record.point + hit_record.normal + Vec3::new(r[0], r[1], r[2]);
+    fn scatter(&self, _: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let target = hit_record.point + hit_record.normal + sample_unit_sphere(sampler.next_2d());
         Some(Ray::new(hit_record.point, target - hit_record.point))
     }
 
-    fn get_attenuation(&self) -> Color {
-        self.color
+    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+        self.texture
+            .get_color(hit_record.u, hit_record.v, &hit_record.point)
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Color {
+        self.get_attenuation(hit_record) * self.pdf(ray, hit_record, direction)
+    }
+
+    fn pdf(&self, _: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        diffuse_pdf(hit_record, direction)
     }
 }
 
 impl Diffuse {
     /// Creates a new diffuse material.
-    pub fn new(color: Color) -> Self {
-        Diffuse { color }
+    pub fn new<T>(texture: T) -> Self
+    where
+        T: Texture + Send + Sync + 'static,
+    {
+        Diffuse {
+            texture: Box::new(texture),
+        }
     }
 }
 
 /// A pure reflective Material.
 pub struct Metal {
-    color: Color,
+    texture: BoxTexture,
     fuzziness: f64,
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<Ray> {
-        let mut target =
-            ray.direction - hit_record.normal * 2.0 * dot(&ray.direction, &hit_record.normal);
-        let r = UnitSphere.sample(&mut rand::thread_rng());
-        target += Vec3::new(r[0], r[1], r[2]) * self.fuzziness;
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let target = reflect(&ray.direction, &hit_record.normal)
+            + sample_unit_sphere(sampler.next_2d()) * self.fuzziness;
         Some(Ray::new(hit_record.point, target))
     }
 
-    fn get_attenuation(&self) -> Color {
-        self.color
+    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+        self.texture
+            .get_color(hit_record.u, hit_record.v, &hit_record.point)
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Color {
+        if dot(direction, &hit_record.normal) <= 0.0 {
+            return Color::new(0, 0, 0);
+        }
+        self.get_attenuation(hit_record) * self.pdf(ray, hit_record, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        fuzzy_reflection_pdf(ray, hit_record, direction, self.fuzziness)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.fuzziness <= 0.0
     }
 }
 
 impl Metal {
     /// Creates a new metal material.
-    pub fn new(color: Color, fuzziness: f64) -> Self {
-        Metal { color, fuzziness }
+    pub fn new<T>(texture: T, fuzziness: f64) -> Self
+    where
+        T: Texture + Send + Sync + 'static,
+    {
+        Metal {
+            texture: Box::new(texture),
+            fuzziness,
+        }
     }
 }
 
 /// A mix of the Diffuse and Metal Materials
+///
+/// Without fuzziness, the metal part is a perfect mirror. The lights are still sampled directly for the diffuse part.
+///
+/// # Example
+/// ```
+/// # use raytracer::material::{DiffuseMetal, Material};
+/// # use raytracer::utils::Color;
+/// let polished = DiffuseMetal::new(Color::new(200, 200, 200), 0.0, 0.5);
+/// assert!(!polished.is_specular());
+///
+/// let mirror = DiffuseMetal::new(Color::new(200, 200, 200), 0.0, 0.0);
+/// assert!(mirror.is_specular());
+/// ```
 pub struct DiffuseMetal {
-    color: Color,
+    texture: BoxTexture,
     fuzziness: f64,
     diffuse_part: f64,
 }
 
 impl Material for DiffuseMetal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<Ray> {
-        let choose_reaction: f64 = rand::random();
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let choose_reaction = sampler.next_1d();
         if choose_reaction < self.diffuse_part {
-            let r: [f64; 3] = UnitSphere.sample(&mut rand::thread_rng());
-            let target = hit_record.point + hit_record.normal + Vec3::new(r[0], r[1], r[2]);
+            let target =
+                hit_record.point + hit_record.normal + sample_unit_sphere(sampler.next_2d());
             Some(Ray::new(hit_record.point, target - hit_record.point))
         } else {
-            let mut target =
-                ray.direction - hit_record.normal * 2.0 * dot(&ray.direction, &hit_record.normal);
-            let r = UnitSphere.sample(&mut rand::thread_rng());
-            target += Vec3::new(r[0], r[1], r[2]) * self.fuzziness;
+            let target = reflect(&ray.direction, &hit_record.normal)
+                + sample_unit_sphere(sampler.next_2d()) * self.fuzziness;
             Some(Ray::new(hit_record.point, target))
         }
     }
 
-    fn get_attenuation(&self) -> Color {
-        self.color
+    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+        self.texture
+            .get_color(hit_record.u, hit_record.v, &hit_record.point)
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Color {
+        if dot(direction, &hit_record.normal) <= 0.0 {
+            return Color::new(0, 0, 0);
+        }
+        self.get_attenuation(hit_record) * self.pdf(ray, hit_record, direction)
+    }
+
+    /// Without fuzziness the reflection is a perfect mirror, a delta component whose density can't be evaluated:
+    /// the density is zero in the mirror direction, so that the reflected rays are weighted by the attenuation,
+    /// and only covers the diffuse part in the other directions.
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        if self.fuzziness <= 0.0 && is_mirror_direction(ray, hit_record, direction) {
+            return 0.0;
+        }
+        self.diffuse_part * diffuse_pdf(hit_record, direction)
+            + (1.0 - self.diffuse_part)
+                * fuzzy_reflection_pdf(ray, hit_record, direction, self.fuzziness)
+    }
+
+    // Only a perfect mirror without diffuse part can't be lit by the direct sampling of the lights
+    fn is_specular(&self) -> bool {
+        self.fuzziness <= 0.0 && self.diffuse_part <= 0.0
     }
 }
 
 impl DiffuseMetal {
     /// Creates a new diffuse and metal Material.
-    pub fn new(color: Color, fuzziness: f64, diffuse_part: f64) -> Self {
-        if diffuse_part < 0.0 || diffuse_part > 1.0 {
+    pub fn new<T>(texture: T, fuzziness: f64, diffuse_part: f64) -> Self
+    where
+        T: Texture + Send + Sync + 'static,
+    {
+        if !(0.0..=1.0).contains(&diffuse_part) {
             panic!("The diffuse_part parameter should be between 0.0 and 1.0 as it represents the part of light that is diffused.")
         }
         DiffuseMetal {
-            color,
+            texture: Box::new(texture),
             fuzziness,
             diffuse_part,
         }
@@ -114,13 +227,27 @@ impl DiffuseMetal {
 }
 
 /// A pure glass Material.
+///
+/// The color tints the light at each reflection and refraction, whatever the thickness of the object.
+/// For tinted glass and liquids, the absorption instead tints the light according to the distance it travels inside the object,
+/// following the Beer-Lambert law. It only works with closed shapes whose inside can be hit, like spheres.
+///
+/// # Example
+/// ```
+/// # use raytracer::material::Dielectric;
+/// # use raytracer::utils::Color;
+/// // White light going through 2 units of this glass comes out green
+/// let glass = Dielectric::new(Color::new(255, 255, 255), 1.5)
+///     .set_absorption(Color::new(100, 200, 120), 2.0);
+/// ```
 pub struct Dielectric {
-    color: Color,
+    texture: BoxTexture,
     refraction: f64,
+    absorption: Color,
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<Ray> {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<Ray> {
         let refraction_ratio = if hit_record.front_face {
             1.0 / self.refraction
         } else {
@@ -130,10 +257,11 @@ impl Material for Dielectric {
         let cos_theta = dot(&-normalized_dir, &hit_record.normal);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
+        let choose_reaction = sampler.next_1d();
         let target = if refraction_ratio * sin_theta > 1.0
-            || self.reflectance(cos_theta, refraction_ratio) > thread_rng().gen()
+            || self.reflectance(cos_theta, refraction_ratio) > choose_reaction
         {
-            ray.direction - hit_record.normal * 2.0 * dot(&ray.direction, &hit_record.normal)
+            reflect(&ray.direction, &hit_record.normal)
         } else {
             let r_perp = (normalized_dir + hit_record.normal * cos_theta) * refraction_ratio;
             let r_par = -hit_record.normal * (1.0 - r_perp.len_squared()).abs().sqrt();
@@ -143,21 +271,60 @@ impl Material for Dielectric {
         Some(Ray::new(hit_record.point, target))
     }
 
-    fn get_attenuation(&self) -> Color {
-        self.color
+    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+        self.texture
+            .get_color(hit_record.u, hit_record.v, &hit_record.point)
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    /// The ray hitting the back of a surface comes from inside the object, where it was absorbed along the distance it travelled.
+    fn transmittance(&self, ray: &Ray, hit_record: &HitRecord) -> Color {
+        if hit_record.front_face {
+            return Color::new(255, 255, 255);
+        }
+        let distance = hit_record.t * ray.direction.len();
+        Color {
+            r: (-self.absorption.r * distance).exp(),
+            g: (-self.absorption.g * distance).exp(),
+            b: (-self.absorption.b * distance).exp(),
+        }
     }
 }
 
 impl Dielectric {
     /// Creates a new dielectric material.
-    pub fn new(color: Color, refraction: f64) -> Self {
-        Dielectric { color, refraction }
+    pub fn new<T>(texture: T, refraction: f64) -> Self
+    where
+        T: Texture + Send + Sync + 'static,
+    {
+        Dielectric {
+            texture: Box::new(texture),
+            refraction,
+            absorption: Color::new(0, 0, 0),
+        }
+    }
+
+    /// Sets the absorption of the light inside the Dielectric: white light travelling distance inside it comes out with the given color.
+    pub fn set_absorption(mut self, color: Color, distance: f64) -> Self {
+        if distance <= 0.0 {
+            panic!("The absorption distance of a Dielectric must be positive");
+        }
+        let coefficient = |channel: f64| -channel.max(1e-6).ln() / distance;
+        self.absorption = Color {
+            r: coefficient(color.r),
+            g: coefficient(color.g),
+            b: coefficient(color.b),
+        };
+        self
     }
 
     fn reflectance(&self, cos_theta: f64, refraction_ratio: f64) -> f64 {
         let mut r0 = (1.0 - refraction_ratio) / (1.0 + refraction_ratio);
         r0 = r0 * r0;
-        r0 + (1.0 - r0) * (1.0 - cos_theta).pow(5)
+        r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5)
     }
 }
 
@@ -167,7 +334,7 @@ pub struct DiffuseLight {
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, _: &Ray, _: &HitRecord) -> Option<Ray> {
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut dyn Sampler) -> Option<Ray> {
         None
     }
 
@@ -175,7 +342,11 @@ impl Material for DiffuseLight {
         self.color * self.intensity
     }
 
-    fn get_attenuation(&self) -> Color {
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn get_attenuation(&self, _: &HitRecord) -> Color {
         Color::new(255, 255, 255)
     }
 }
@@ -185,3 +356,50 @@ impl DiffuseLight {
         DiffuseLight { color, intensity }
     }
 }
+
+/// Reflects the direction around the normal. The reflected direction is normalized.
+fn reflect(direction: &Vec3, normal: &Vec3) -> Vec3 {
+    let direction = direction.normalize();
+    direction - *normal * 2.0 * dot(&direction, normal)
+}
+
+/// Density of the cosine weighted directions around the normal, sampled by the diffuse Materials.
+fn diffuse_pdf(hit_record: &HitRecord, direction: &Vec3) -> f64 {
+    let cos_theta = dot(&direction.normalize(), &hit_record.normal);
+    cos_theta.max(0.0) / PI
+}
+
+/// Returns true if the direction is the mirror reflection of the ray.
+fn is_mirror_direction(ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> bool {
+    let reflected = reflect(&ray.direction, &hit_record.normal);
+    (direction.normalize() - reflected.normalize()).len() < 1e-9
+}
+
+/// Density of the directions sampled by the fuzzy reflection of the ray, which offsets the reflected direction
+/// by a uniform point of a sphere of radius fuzziness.
+fn fuzzy_reflection_pdf(
+    ray: &Ray,
+    hit_record: &HitRecord,
+    direction: &Vec3,
+    fuzziness: f64,
+) -> f64 {
+    if fuzziness <= 0.0 {
+        return 0.0;
+    }
+    // The direction crosses the fuzziness sphere centered on the reflected direction at distances t1 and t2.
+    // Each crossing contributes t² / (4π fuzziness² |cos|), cos being the angle between the direction and the sphere normal.
+    let reflected = reflect(&ray.direction, &hit_record.normal);
+    let direction = direction.normalize();
+    let b = dot(&direction, &reflected);
+    let discriminant = b * b - 1.0 + fuzziness * fuzziness;
+    if discriminant <= 0.0 {
+        return 0.0;
+    }
+    let root = discriminant.sqrt();
+    let sum: f64 = [b + root, b - root]
+        .iter()
+        .filter(|t| **t > 0.0)
+        .map(|t| t * t)
+        .sum();
+    sum / (4.0 * PI * fuzziness * root)
+}
@@ -0,0 +1,173 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::{seeded_rng, Sampler};
+use crate::shapes::collide::HitRecord;
+use crate::utils::{dot, Base, Color, Vec3};
+
+/// A homogeneous participating medium, like fog, smoke or murky water.
+///
+/// The light going through it is absorbed and scattered with the given coefficients, which are the probabilities per unit distance
+/// of each event. The scattered light is tinted by the color of the Medium and its direction follows the Henyey-Greenstein phase function,
+/// whose anisotropy goes from -1 (backward scattering) to 1 (forward scattering). The default Medium is white and isotropic.
+///
+/// A Medium can fill a shape with the ConstantMedium shape, or the whole Scene with SceneBuilder::set_medium.
+/// The rays going through it are stopped at a random distance, where the Medium acts as the Material of the hit.
+///
+/// # Example
+/// ```
+/// # use raytracer::medium::Medium;
+/// # use raytracer::utils::Color;
+/// let fog = Medium::new(0.01, 0.05)
+///     .set_color(Color::new(230, 235, 255))
+///     .set_anisotropy(0.6);
+/// ```
+pub struct Medium {
+    absorption: f64,
+    scattering: f64,
+    color: Color,
+    anisotropy: f64,
+}
+
+impl Medium {
+    /// Creates a new Medium with the given absorption and scattering coefficients.
+    pub fn new(absorption: f64, scattering: f64) -> Self {
+        if absorption < 0.0 || scattering < 0.0 {
+            panic!("The absorption and scattering coefficients of a Medium can't be negative");
+        }
+        Medium {
+            absorption,
+            scattering,
+            color: Color::new(255, 255, 255),
+            anisotropy: 0.0,
+        }
+    }
+
+    /// Sets the color of the light scattered by the Medium.
+    pub fn set_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the anisotropy of the Henyey-Greenstein phase function, between -1 and 1.
+    pub fn set_anisotropy(mut self, anisotropy: f64) -> Self {
+        if anisotropy <= -1.0 || anisotropy >= 1.0 {
+            panic!("The anisotropy of a Medium must be strictly between -1 and 1");
+        }
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    /// Returns the probability per unit distance that the light is absorbed or scattered.
+    pub(crate) fn extinction(&self) -> f64 {
+        self.absorption + self.scattering
+    }
+
+    /// Returns the values identifying the coefficients of the Medium, to salt the random numbers of the rays going through it.
+    pub(crate) fn get_salt(&self) -> [u64; 2] {
+        [self.absorption.to_bits(), self.scattering.to_bits()]
+    }
+
+    /// Value of the phase function for the cosine of the angle between the incoming and the scattered directions.
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.anisotropy;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+}
+
+impl Material for Medium {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let g = self.anisotropy;
+        let (u, v) = sampler.next_2d();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let direction = Base::from_w(ray.direction).to_world(local);
+        Some(Ray::new(hit_record.point, direction))
+    }
+
+    /// The scattering albedo: the part of the light stopped by the Medium that is scattered rather than absorbed.
+    fn get_attenuation(&self, _: &HitRecord) -> Color {
+        let extinction = self.extinction();
+        if extinction <= 0.0 {
+            return Color::new(0, 0, 0);
+        }
+        self.color * (self.scattering / extinction)
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Color {
+        self.get_attenuation(hit_record) * self.pdf(ray, hit_record, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, _: &HitRecord, direction: &Vec3) -> f64 {
+        self.phase(dot(&ray.direction.normalize(), &direction.normalize()))
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+}
+
+/// Samples the distance the ray travels in the Medium before being absorbed or scattered (free-flight distance).
+/// Returns the HitRecord of this point if it is between t_min and t_max, with the Medium as its material.
+pub(crate) fn sample_medium_hit(
+    medium: &Arc<Medium>,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord> {
+    let length = ray.direction.len();
+    if medium.extinction() <= 0.0 || length <= 0.0 {
+        return None;
+    }
+    let [absorption, scattering] = medium.get_salt();
+    let mut rng = ray_rng(ray, &[t_min.to_bits(), absorption, scattering]);
+    let distance = -(1.0 - rng.gen::<f64>()).ln() / medium.extinction();
+
+    let t = t_min + distance / length;
+    if t >= t_max {
+        return None;
+    }
+    Some(medium_hit(medium, ray, t))
+}
+
+/// Returns the fraction of the light going through the Medium along the ray between t_min and t_max (Beer-Lambert law).
+pub(crate) fn medium_transmittance(medium: &Medium, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+    let distance = (t_max - t_min).max(0.0) * ray.direction.len();
+    (-medium.extinction() * distance).exp()
+}
+
+/// Returns the HitRecord of the point of the ray at t, scattered by the Medium.
+pub(crate) fn medium_hit(medium: &Arc<Medium>, ray: &Ray, t: f64) -> HitRecord {
+    HitRecord::new(
+        ray.at(t),
+        -ray.direction.normalize(),
+        t,
+        true,
+        medium.clone(),
+    )
+}
+
+/// Returns a random number generator seeded with a hash of the ray and of the salt values.
+///
+/// The media have no Sampler to sample the distances travelled in them, so the same ray always stops at the same point.
+/// The salt must identify the medium, so that overlapping media stop the ray independently.
+pub(crate) fn ray_rng(ray: &Ray, salt: &[u64]) -> SmallRng {
+    let [ox, oy, oz] = ray.origin.to_bits();
+    let [dx, dy, dz] = ray.direction.to_bits();
+    let mut values = vec![ox, oy, oz, dx, dy, dz];
+    values.extend_from_slice(salt);
+    seeded_rng(&values)
+}
@@ -0,0 +1,406 @@
+use std::f64::consts::PI;
+
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::{sample_unit_sphere, Sampler};
+use crate::shapes::collide::HitRecord;
+use crate::texture::Texture;
+use crate::utils::{cross, dot, Base, Color, Vec3};
+
+type BoxTexture = Box<dyn Texture + Send + Sync>;
+
+/// The distribution of the orientations of the microfacets of a Microfacet Material.
+///
+/// Ggx has long tails, giving highlights a glow around them. Beckmann gives sharper highlights.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MicrofacetDistribution {
+    Ggx,
+    Beckmann,
+}
+
+/// How the Fresnel reflectance of a Microfacet Material is computed.
+///
+/// Schlick is the usual approximation. Exact uses the Fresnel equations of a dielectric of the refraction index of the Material
+/// for its non-metallic part; the metallic part always uses the Schlick approximation from the color of the Material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fresnel {
+    Schlick,
+    Exact,
+}
+
+/// A physically based Material made of microscopic mirrors (Cook-Torrance model).
+///
+/// The roughness, between 0 and 1, spreads the orientations of the microfacets: 0 is polished, 1 is fully rough.
+/// The metallic parameter, between 0 and 1, goes from a dielectric, like plastic, to a metal.
+/// A dielectric reflects white highlights on top of its diffuse color, while a metal tints its reflections with its color.
+/// The shadowing and masking of the microfacets follow the Smith model, and the reflected directions are sampled
+/// among the microfacets visible from the incident ray. The default distribution is GGX, with Schlick Fresnel.
+///
+/// # Example
+/// ```
+/// # use raytracer::microfacet::{Fresnel, Microfacet, MicrofacetDistribution};
+/// # use raytracer::utils::Color;
+/// let gold = Microfacet::new(Color::new(255, 190, 80), 0.3, 1.0);
+/// let plastic = Microfacet::new(Color::new(200, 30, 30), 0.2, 0.0)
+///     .set_distribution(MicrofacetDistribution::Beckmann)
+///     .set_fresnel(Fresnel::Exact)
+///     .set_refraction(1.45);
+/// ```
+pub struct Microfacet {
+    texture: BoxTexture,
+    roughness: f64,
+    metallic: f64,
+    distribution: MicrofacetDistribution,
+    fresnel: Fresnel,
+    refraction: f64,
+}
+
+impl Microfacet {
+    /// Creates a new Microfacet Material of the given color, roughness and metallic parameters.
+    pub fn new<T>(texture: T, roughness: f64, metallic: f64) -> Self
+    where
+        T: Texture + Send + Sync + 'static,
+    {
+        if !(0.0..=1.0).contains(&roughness) {
+            panic!("The roughness of a Microfacet Material must be between 0.0 and 1.0");
+        }
+        if !(0.0..=1.0).contains(&metallic) {
+            panic!("The metallic parameter of a Microfacet Material must be between 0.0 and 1.0");
+        }
+        Microfacet {
+            texture: Box::new(texture),
+            roughness,
+            metallic,
+            distribution: MicrofacetDistribution::Ggx,
+            fresnel: Fresnel::Schlick,
+            refraction: 1.5,
+        }
+    }
+
+    /// Sets the distribution of the orientations of the microfacets.
+    pub fn set_distribution(mut self, distribution: MicrofacetDistribution) -> Self {
+        self.distribution = distribution;
+        self
+    }
+
+    /// Sets how the Fresnel reflectance is computed.
+    pub fn set_fresnel(mut self, fresnel: Fresnel) -> Self {
+        self.fresnel = fresnel;
+        self
+    }
+
+    /// Sets the refraction index of the non-metallic part of the Material, 1.5 by default.
+    pub fn set_refraction(mut self, refraction: f64) -> Self {
+        if refraction < 1.0 {
+            panic!("The refraction index of a Microfacet Material can't be lower than 1.0");
+        }
+        self.refraction = refraction;
+        self
+    }
+
+    /// Width of the distribution of the microfacets. Very low values are clamped, as a perfect mirror can't be evaluated.
+    fn alpha(&self) -> f64 {
+        (self.roughness * self.roughness).max(1e-3)
+    }
+
+    /// Density of the microfacets oriented along the local direction h.
+    fn distribution(&self, h: &Vec3) -> f64 {
+        let alpha2 = self.alpha() * self.alpha();
+        let cos2 = h.z * h.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        match self.distribution {
+            MicrofacetDistribution::Ggx => {
+                let denominator = cos2 * (alpha2 - 1.0) + 1.0;
+                alpha2 / (PI * denominator * denominator)
+            }
+            MicrofacetDistribution::Beckmann => {
+                let tan2 = (1.0 - cos2) / cos2;
+                (-tan2 / alpha2).exp() / (PI * alpha2 * cos2 * cos2)
+            }
+        }
+    }
+
+    /// Smith auxiliary function of the local direction w, giving the masking of the microfacets seen from w.
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        if tan2.is_infinite() {
+            return f64::INFINITY;
+        }
+        let alpha = self.alpha();
+        match self.distribution {
+            MicrofacetDistribution::Ggx => ((1.0 + alpha * alpha * tan2).sqrt() - 1.0) / 2.0,
+            MicrofacetDistribution::Beckmann => {
+                let a = 1.0 / (alpha * tan2.sqrt());
+                if a >= 1.6 {
+                    return 0.0;
+                }
+                (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+            }
+        }
+    }
+
+    /// Samples the normal of a microfacet visible from the local direction wo, using the 2D sample.
+    fn sample_visible_normal(&self, wo: &Vec3, sample: (f64, f64)) -> Vec3 {
+        let alpha = self.alpha();
+        // Direction in the space where the microfacets are stretched to a roughness of 1
+        let stretched = Vec3::new(alpha * wo.x, alpha * wo.y, wo.z).normalize();
+        let normal = match self.distribution {
+            MicrofacetDistribution::Ggx => sample_ggx_visible_normal(&stretched, sample),
+            MicrofacetDistribution::Beckmann => sample_beckmann_visible_normal(&stretched, sample),
+        };
+        Vec3::new(alpha * normal.x, alpha * normal.y, normal.z.max(0.0)).normalize()
+    }
+
+    /// Fresnel reflectance of the non-metallic part of the Material, for a microfacet seen with the given cosine.
+    fn dielectric_reflectance(&self, cos_theta: f64) -> f64 {
+        match self.fresnel {
+            Fresnel::Schlick => {
+                let r0 = ((self.refraction - 1.0) / (self.refraction + 1.0)).powi(2);
+                schlick(cos_theta, r0)
+            }
+            Fresnel::Exact => dielectric_fresnel(cos_theta, self.refraction),
+        }
+    }
+
+    /// Fresnel reflectance of a microfacet seen with the given cosine, the metallic part reflecting the color.
+    fn fresnel(&self, cos_theta: f64, color: Color) -> Color {
+        let white = Color::new(255, 255, 255);
+        let grazing = (1.0 - cos_theta).powi(5);
+        let metal = color * (1.0 - grazing) + white * grazing;
+        white * (self.dielectric_reflectance(cos_theta) * (1.0 - self.metallic))
+            + metal * self.metallic
+    }
+
+    /// Probability to sample the specular reflection rather than the diffuse part, for the local direction wo.
+    fn specular_probability(&self, wo: &Vec3, color: Color) -> f64 {
+        let average = |c: Color| (c.r + c.g + c.b) / 3.0;
+        let specular = average(self.fresnel(wo.z, color));
+        let diffuse = (1.0 - self.metallic) * average(color);
+        if specular + diffuse <= 0.0 {
+            return 1.0;
+        }
+        (specular / (specular + diffuse)).max(0.1)
+    }
+
+    /// Returns the local directions of the incident ray and of the direction, in the base of the normal of the hit.
+    fn to_local(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> (Vec3, Vec3) {
+        let base = Base::from_w(hit_record.normal);
+        let local = |w: Vec3| {
+            let w = w.normalize();
+            Vec3::new(dot(&w, &base.u()), dot(&w, &base.v()), dot(&w, &base.w()))
+        };
+        (local(-ray.direction), local(*direction))
+    }
+}
+
+impl Material for Microfacet {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let base = Base::from_w(hit_record.normal);
+        let direction = -ray.direction.normalize();
+        let wo = Vec3::new(
+            dot(&direction, &base.u()),
+            dot(&direction, &base.v()),
+            dot(&direction, &base.w()),
+        );
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let color = self.get_attenuation(hit_record);
+        let choose_reaction = sampler.next_1d();
+        let sample = sampler.next_2d();
+        if choose_reaction < self.specular_probability(&wo, color) {
+            let h = self.sample_visible_normal(&wo, sample);
+            let wi = h * (2.0 * dot(&wo, &h)) - wo;
+            if wi.z <= 0.0 {
+                // The reflection on the microfacet is shadowed by the other ones
+                return None;
+            }
+            Some(Ray::new(hit_record.point, base.to_world(wi)))
+        } else {
+            let target = hit_record.normal + sample_unit_sphere(sample);
+            Some(Ray::new(hit_record.point, target))
+        }
+    }
+
+    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+        self.texture
+            .get_color(hit_record.u, hit_record.v, &hit_record.point)
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Color {
+        let (wo, wi) = self.to_local(ray, hit_record, direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new(0, 0, 0);
+        }
+        let h = (wo + wi).normalize();
+        let cos_h = dot(&wo, &h).max(0.0);
+        let color = self.get_attenuation(hit_record);
+
+        // Height-correlated Smith shadowing and masking
+        let shadowing = 1.0 / (1.0 + self.lambda(&wo) + self.lambda(&wi));
+        let fresnel = self.fresnel(cos_h, color);
+        let specular = fresnel * (self.distribution(&h) * shadowing / (4.0 * wo.z));
+
+        // The light that isn't reflected by the dielectric part enters it and is diffused
+        let transmitted = (1.0 - self.metallic) * (1.0 - self.dielectric_reflectance(cos_h));
+        let diffuse = color * (transmitted * wi.z / PI);
+        specular + diffuse
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        let (wo, wi) = self.to_local(ray, hit_record, direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        let visible_normals = self.distribution(&h) / ((1.0 + self.lambda(&wo)) * wo.z);
+        let specular_pdf = visible_normals / 4.0;
+        let diffuse_pdf = wi.z / PI;
+        let p = self.specular_probability(&wo, self.get_attenuation(hit_record));
+        p * specular_pdf + (1.0 - p) * diffuse_pdf
+    }
+}
+
+/// Schlick approximation of the Fresnel reflectance, from the reflectance r0 at normal incidence.
+fn schlick(cos_theta: f64, r0: f64) -> f64 {
+    r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5)
+}
+
+/// Fresnel reflectance of unpolarized light coming from the air on a dielectric of the given refraction index.
+fn dielectric_fresnel(cos_theta: f64, refraction: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin_t = (1.0 - cos_i * cos_i).max(0.0).sqrt() / refraction;
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin_t * sin_t).sqrt();
+    let parallel = (refraction * cos_i - cos_t) / (refraction * cos_i + cos_t);
+    let perpendicular = (cos_i - refraction * cos_t) / (cos_i + refraction * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// Samples the normal of a GGX microfacet of roughness 1 visible from the direction, following Heitz (2018).
+fn sample_ggx_visible_normal(direction: &Vec3, (u, v): (f64, f64)) -> Vec3 {
+    let length_squared = direction.x * direction.x + direction.y * direction.y;
+    let t1 = if length_squared > 0.0 {
+        Vec3::new(-direction.y, direction.x, 0.0) / length_squared.sqrt()
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = cross(direction, &t1);
+
+    // Uniform point of a disk, squeezed toward the half visible from the direction
+    let r = u.sqrt();
+    let phi = 2.0 * PI * v;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + direction.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    t1 * p1 + t2 * p2 + *direction * p3
+}
+
+/// Samples the normal of a Beckmann microfacet of roughness 1 visible from the direction, following Jakob (2014).
+fn sample_beckmann_visible_normal(direction: &Vec3, (u, v): (f64, f64)) -> Vec3 {
+    let cos_theta = direction.z;
+    let (slope_x, slope_y) = if cos_theta > 0.9999 {
+        // Seen from the normal, all the microfacets are visible
+        let r = (-(1.0 - u).ln()).sqrt();
+        let phi = 2.0 * PI * v;
+        (r * phi.cos(), r * phi.sin())
+    } else {
+        // Inverts the distribution of the slopes along the direction with the Newton method
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let tan_theta = sin_theta / cos_theta;
+        let cot_theta = 1.0 / tan_theta;
+        let sqrt_pi_inv = 1.0 / PI.sqrt();
+        let (mut a, mut c) = (-1.0, erf(cot_theta));
+        let u = u.max(1e-6);
+        let theta = cos_theta.acos();
+        let fit = 1.0 + theta * (-0.876 + theta * (0.4265 - 0.0594 * theta));
+        let mut b = c - (1.0 + c) * (1.0 - u).powf(fit);
+        let normalization =
+            1.0 / (1.0 + c + sqrt_pi_inv * tan_theta * (-cot_theta * cot_theta).exp());
+        for _ in 0..10 {
+            if !(a..=c).contains(&b) {
+                b = 0.5 * (a + c);
+            }
+            let inverse = erf_inv(b);
+            let value = normalization
+                * (1.0 + b + sqrt_pi_inv * tan_theta * (-inverse * inverse).exp())
+                - u;
+            if value.abs() < 1e-5 {
+                break;
+            }
+            if value > 0.0 {
+                c = b;
+            } else {
+                a = b;
+            }
+            let derivative = normalization * (1.0 - inverse * tan_theta);
+            b -= value / derivative;
+        }
+        (erf_inv(b), erf_inv(2.0 * v.max(1e-6) - 1.0))
+    };
+
+    // Rotates the slopes to the azimuth of the direction
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let (cos_phi, sin_phi) = if sin_theta > 0.0 {
+        (direction.x / sin_theta, direction.y / sin_theta)
+    } else {
+        (1.0, 0.0)
+    };
+    let x = cos_phi * slope_x - sin_phi * slope_y;
+    let y = sin_phi * slope_x + cos_phi * slope_y;
+    Vec3::new(-x, -y, 1.0).normalize()
+}
+
+/// Error function, with the approximation 7.1.26 of Abramowitz and Stegun.
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - polynomial * (-x * x).exp())
+}
+
+/// Inverse of the error function, with the approximation of Giles (2010).
+fn erf_inv(x: f64) -> f64 {
+    let x = x.clamp(-0.99999, 0.99999);
+    let w = -((1.0 - x) * (1.0 + x)).ln();
+    let p = if w < 5.0 {
+        let w = w - 2.5;
+        [
+            3.43273939e-07,
+            -3.5233877e-06,
+            -4.39150654e-06,
+            0.00021858087,
+            -0.00125372503,
+            -0.00417768164,
+            0.246640727,
+            1.50140941,
+        ]
+        .iter()
+        .fold(2.81022636e-08, |p, coefficient| coefficient + p * w)
+    } else {
+        let w = w.sqrt() - 3.0;
+        [
+            0.000100950558,
+            0.00134934322,
+            -0.00367342844,
+            0.00573950773,
+            -0.0076224613,
+            0.00943887047,
+            1.00167406,
+            2.83297682,
+        ]
+        .iter()
+        .fold(-0.000200214257, |p, coefficient| coefficient + p * w)
+    };
+    p * x
+}
@@ -0,0 +1,280 @@
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shapes::collide::HitRecord;
+use crate::texture::Texture;
+use crate::utils::{cross, dot, Color, Vec3};
+
+type BoxTexture = Box<dyn Texture + Send + Sync>;
+
+/// Implement this trait for the maps that add details to a surface by perturbing its shading normal.
+///
+/// The maps work in the tangent frame given by the dpdu and dpdv tangents of the hit.
+///
+/// # Example
+/// ```
+/// # use raytracer::camera::Camera;
+/// # use raytracer::normal_map::{BumpMap, NormalMap, NormalPerturbation};
+/// # use raytracer::sampler::SamplerKind;
+/// # use raytracer::shapes::collide::Collide;
+/// # use raytracer::shapes::sphere::Sphere;
+/// # use raytracer::texture::Texture;
+/// # use raytracer::utils::{dot, Color, Vec3};
+/// let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0);
+/// let camera = Camera::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 1.0);
+/// let mut sampler = SamplerKind::Random.create(1, 0);
+/// let ray = camera.get_ray(0.55, 0.6, sampler.as_mut());
+/// let hit = sphere.get_intersection(&ray, 0.0001, f64::INFINITY).unwrap();
+///
+/// // The tangent frame of the Sphere follows its spherical mapping: u goes around the vertical axis, v goes up
+/// assert!(hit.dpdu.y.abs() < 1e-9);
+/// assert!(hit.dpdv.y > 0.0);
+///
+/// // A flat normal map keeps the normal of the surface
+/// let flat = NormalMap::new(Color { r: 0.5, g: 0.5, b: 1.0 });
+/// assert!((flat.perturb(&hit) - hit.normal).len() < 1e-9);
+///
+/// // A height rising with u tilts the normal against dpdu
+/// struct Ramp;
+/// impl Texture for Ramp {
+///     fn get_color(&self, u: f64, _: f64, _: &Vec3) -> Color {
+///         Color { r: u, g: u, b: u }
+///     }
+/// }
+/// let normal = BumpMap::new(Ramp, 0.5).perturb(&hit);
+/// assert!(dot(&normal, &hit.dpdu) < 0.0);
+/// assert!(dot(&normal, &hit.normal) > 0.0);
+/// ```
+pub trait NormalPerturbation {
+    /// Returns the perturbed normal at the hit, normalized, on the same side of the surface as the normal of the hit.
+    fn perturb(&self, hit_record: &HitRecord) -> Vec3;
+}
+
+/// A tangent-space normal map: each color of the texture holds the coordinates of the normal in the tangent frame of the surface.
+///
+/// The red and green channels go along the dpdu and dpdv tangents of the hit, the blue channel along the normal,
+/// each channel mapping [0, 1] to [-1, 1]. The texture must be read without transfer function.
+///
+/// # Example
+/// ```no_run
+/// # use raytracer::color_management::TransferFunction;
+/// # use raytracer::normal_map::NormalMap;
+/// # use raytracer::texture::ImageTexture;
+/// let bricks = NormalMap::new(
+///     ImageTexture::new("textures/bricks_normal.png").set_transfer_function(TransferFunction::Linear),
+/// );
+/// ```
+pub struct NormalMap {
+    texture: BoxTexture,
+    strength: f64,
+}
+
+impl NormalMap {
+    /// Creates a new NormalMap reading the normals from the texture.
+    pub fn new<T>(texture: T) -> Self
+    where
+        T: Texture + Send + Sync + 'static,
+    {
+        NormalMap {
+            texture: Box::new(texture),
+            strength: 1.0,
+        }
+    }
+
+    /// Scales the tangent part of the normals of the map. 0 gives back the normal of the surface.
+    pub fn set_strength(mut self, strength: f64) -> Self {
+        self.strength = strength;
+        self
+    }
+}
+
+impl NormalPerturbation for NormalMap {
+    fn perturb(&self, hit_record: &HitRecord) -> Vec3 {
+        let normal = outward_normal(hit_record);
+        let color = self
+            .texture
+            .get_color(hit_record.u, hit_record.v, &hit_record.point);
+
+        // Orthonormal tangent frame following the texture coordinates
+        let tangent = hit_record.dpdu - normal * dot(&hit_record.dpdu, &normal);
+        if tangent.len() <= 1e-12 {
+            return hit_record.normal;
+        }
+        let tangent = tangent.normalize();
+        let mut bitangent = cross(&normal, &tangent);
+        if dot(&bitangent, &hit_record.dpdv) < 0.0 {
+            bitangent = -bitangent;
+        }
+
+        let perturbed = tangent * ((2.0 * color.r - 1.0) * self.strength)
+            + bitangent * ((2.0 * color.g - 1.0) * self.strength)
+            + normal * (2.0 * color.b - 1.0).max(0.0);
+        if perturbed.len() <= 1e-12 {
+            return hit_record.normal;
+        }
+        same_side(perturbed.normalize(), hit_record)
+    }
+}
+
+/// A bump map: the surface is displaced along its normal by the height given by the brightness of the texture.
+///
+/// The scale is the height of the white parts of the texture, in units of distance of the Scene.
+/// Solid textures evaluated at the hit point add details to the shapes that have no texture coordinates.
+///
+/// # Example
+/// ```
+/// # use raytracer::normal_map::BumpMap;
+/// # use raytracer::texture::Noise;
+/// # use raytracer::utils::Color;
+/// let hammered = BumpMap::new(Noise::new(Color::new(0, 0, 0), Color::new(255, 255, 255), 20.0), 0.01);
+/// ```
+pub struct BumpMap {
+    height: BoxTexture,
+    scale: f64,
+}
+
+impl BumpMap {
+    /// Creates a new BumpMap reading the heights from the texture, multiplied by scale.
+    pub fn new<T>(height: T, scale: f64) -> Self
+    where
+        T: Texture + Send + Sync + 'static,
+    {
+        BumpMap {
+            height: Box::new(height),
+            scale,
+        }
+    }
+
+    fn get_height(&self, u: f64, v: f64, point: &Vec3) -> f64 {
+        let color = self.height.get_color(u, v, point);
+        self.scale * (color.r + color.g + color.b) / 3.0
+    }
+}
+
+impl NormalPerturbation for BumpMap {
+    fn perturb(&self, hit_record: &HitRecord) -> Vec3 {
+        let normal = outward_normal(hit_record);
+        let (u, v, point) = (hit_record.u, hit_record.v, hit_record.point);
+
+        // Finite differences of the height along the texture coordinates
+        let delta = 0.0005;
+        let height = self.get_height(u, v, &point);
+        let height_u = self.get_height(u + delta, v, &(point + hit_record.dpdu * delta));
+        let height_v = self.get_height(u, v + delta, &(point + hit_record.dpdv * delta));
+
+        // Tangents of the displaced surface, neglecting the variation of the normal
+        let dpdu = hit_record.dpdu + normal * ((height_u - height) / delta);
+        let dpdv = hit_record.dpdv + normal * ((height_v - height) / delta);
+        let mut perturbed = cross(&dpdu, &dpdv);
+        if perturbed.len() <= 1e-12 {
+            return hit_record.normal;
+        }
+        // The tangents of mirrored texture coordinates give the inner normal
+        if dot(&cross(&hit_record.dpdu, &hit_record.dpdv), &normal) < 0.0 {
+            perturbed = -perturbed;
+        }
+        same_side(perturbed.normalize(), hit_record)
+    }
+}
+
+/// A Material whose shading normal is perturbed by a NormalMap or a BumpMap before it scatters and evaluates the light.
+///
+/// Only the shading of the Material is changed: the shape keeps its geometry, so its silhouette and shadows are those of the smooth surface.
+///
+/// # Example
+/// ```
+/// # use raytracer::material::Metal;
+/// # use raytracer::normal_map::{BumpMap, NormalMapped};
+/// # use raytracer::texture::Turbulence;
+/// # use raytracer::utils::Color;
+/// let bumps = BumpMap::new(Turbulence::new(Color::new(0, 0, 0), Color::new(255, 255, 255), 8.0), 0.02);
+/// let material = NormalMapped::new(Metal::new(Color::new(200, 180, 120), 0.1), bumps);
+/// ```
+pub struct NormalMapped {
+    material: Box<dyn Material + Send + Sync>,
+    map: Box<dyn NormalPerturbation + Send + Sync>,
+}
+
+impl NormalMapped {
+    /// Creates a new NormalMapped Material, perturbing the normal of the material with the map.
+    pub fn new<M, N>(material: M, map: N) -> Self
+    where
+        M: Material + Send + Sync + 'static,
+        N: NormalPerturbation + Send + Sync + 'static,
+    {
+        NormalMapped {
+            material: Box::new(material),
+            map: Box::new(map),
+        }
+    }
+
+    /// Returns the hit with its normal replaced by the perturbed one.
+    /// The geometric normal is kept if the ray would come from below the perturbed surface.
+    fn perturb(&self, ray: &Ray, hit_record: &HitRecord) -> HitRecord {
+        let normal = self.map.perturb(hit_record);
+        let mut perturbed = hit_record.clone();
+        if dot(&ray.direction, &normal) < 0.0 {
+            perturbed.normal = normal;
+        }
+        perturbed
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<Ray> {
+        self.material
+            .scatter(ray, &self.perturb(ray, hit_record), sampler)
+    }
+
+    fn emit(&self) -> Color {
+        self.material.emit()
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Color {
+        self.material
+            .eval(ray, &self.perturb(ray, hit_record), direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        self.material
+            .pdf(ray, &self.perturb(ray, hit_record), direction)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn transmittance(&self, ray: &Ray, hit_record: &HitRecord) -> Color {
+        self.material.transmittance(ray, hit_record)
+    }
+
+    fn is_volumetric(&self) -> bool {
+        self.material.is_volumetric()
+    }
+
+    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+        self.material.get_attenuation(hit_record)
+    }
+}
+
+/// Returns the normal of the hit on the outer side of the surface, the side its tangent frame is defined for.
+fn outward_normal(hit_record: &HitRecord) -> Vec3 {
+    if hit_record.front_face {
+        hit_record.normal
+    } else {
+        -hit_record.normal
+    }
+}
+
+/// Flips the outward normal to the side of the normal of the hit.
+fn same_side(normal: Vec3, hit_record: &HitRecord) -> Vec3 {
+    if hit_record.front_face {
+        normal
+    } else {
+        -normal
+    }
+}
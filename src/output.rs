@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageResult, Rgb};
+
+use crate::framebuffer::FrameBuffer;
+
+/// File format of the rendered image.
+///
+/// Png and Jpeg are 8 bits formats, the colors are clamped and quantized when saving them.
+/// Exr (OpenEXR), Hdr (Radiance RGBE) and Pfm (Portable Float Map) are high dynamic range formats,
+/// the linear colors of the render are written as 32 bits floats without any clamping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Exr,
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    /// Returns the OutputFormat matching the extension of the path, if any.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "exr" => Some(OutputFormat::Exr),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the format stores high dynamic range floating point colors.
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Exr | OutputFormat::Hdr | OutputFormat::Pfm
+        )
+    }
+}
+
+/// Writes the FrameBuffer in the Radiance RGBE format.
+pub(crate) fn write_hdr(buffer: &FrameBuffer, path: &str) -> ImageResult<()> {
+    let data: Vec<Rgb<f32>> = buffer
+        .pixels()
+        .iter()
+        .map(|c| Rgb([c.r as f32, c.g as f32, c.b as f32]))
+        .collect();
+    let writer = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(writer).encode(&data, buffer.width() as usize, buffer.height() as usize)
+}
+
+/// Writes the FrameBuffer in the Portable Float Map format.
+/// PFM files store the rows from the bottom to the top of the image.
+pub(crate) fn write_pfm(buffer: &FrameBuffer, path: &str) -> ImageResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    // A negative scale indicates little endian data
+    write!(writer, "PF\n{} {}\n-1.0\n", buffer.width(), buffer.height())?;
+    for y in (0..buffer.height()).rev() {
+        for x in 0..buffer.width() {
+            let color = buffer.get_pixel(x, y);
+            for value in [color.r, color.g, color.b] {
+                writer.write_all(&(value as f32).to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the FrameBuffer as an uncompressed scanline OpenEXR file with 32 bits float R, G and B channels.
+pub(crate) fn write_exr(buffer: &FrameBuffer, path: &str) -> ImageResult<()> {
+    let (width, height) = (buffer.width(), buffer.height());
+
+    let mut header = vec![];
+    header.extend_from_slice(&20000630u32.to_le_bytes()); // Magic number
+    header.extend_from_slice(&2u32.to_le_bytes()); // Version 2, single part scanline file
+
+    // Channels must be sorted alphabetically, each one is stored as a 32 bits float (pixel type 2)
+    let mut channels = vec![];
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved bytes
+        channels.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+        channels.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+    }
+    channels.push(0);
+
+    let mut window = vec![];
+    for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut screen_window_center = vec![];
+    screen_window_center.extend_from_slice(&0f32.to_le_bytes());
+    screen_window_center.extend_from_slice(&0f32.to_le_bytes());
+
+    push_exr_attribute(&mut header, "channels", "chlist", &channels);
+    push_exr_attribute(&mut header, "compression", "compression", &[0]);
+    push_exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    push_exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    push_exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    push_exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    push_exr_attribute(
+        &mut header,
+        "screenWindowCenter",
+        "v2f",
+        &screen_window_center,
+    );
+    push_exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // Each scanline is stored in its own chunk, preceded by its y coordinate and its size in bytes
+    let line_size = width as usize * 3 * 4;
+    let chunk_size = 4 + 4 + line_size;
+    let offsets_start = header.len() + height as usize * 8;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&header)?;
+    for y in 0..height as usize {
+        let offset = (offsets_start + y * chunk_size) as u64;
+        writer.write_all(&offset.to_le_bytes())?;
+    }
+    for y in 0..height {
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&(line_size as i32).to_le_bytes())?;
+        for channel in 0..3 {
+            for x in 0..width {
+                let color = buffer.get_pixel(x, y);
+                let value = match channel {
+                    0 => color.b,
+                    1 => color.g,
+                    _ => color.r,
+                };
+                writer.write_all(&(value as f32).to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn push_exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
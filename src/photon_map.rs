@@ -0,0 +1,217 @@
+use std::f64::consts::PI;
+use std::thread;
+
+use crate::ray::Ray;
+use crate::sampler::{sample_unit_sphere, Sampler};
+use crate::scene::Scene;
+use crate::shapes::collide::HitRecord;
+use crate::utils::{dot, Color, Vec3};
+
+/// Light carried by a photon when it hit a surface.
+struct Photon {
+    point: Vec3,
+    /// Normalized direction in which the photon was travelling
+    direction: Vec3,
+    power: Color,
+    /// Axis along which this photon splits the photons of its subtree in the kd-tree
+    axis: usize,
+}
+
+/// Photons traced from the emissive shapes of the Scene, stored in a kd-tree to quickly find the ones around a point.
+///
+/// The kd-tree is implicit: the photons of each subtree are stored contiguously, the median one splitting
+/// the ones before and after it along its axis.
+///
+/// Only the indirect light is stored, the photons being kept from their second hit on.
+/// The light coming straight from the lights is left to the integrator, which samples it directly.
+pub(crate) struct PhotonMap {
+    photons: Vec<Photon>,
+}
+
+impl PhotonMap {
+    /// Shoots n_photons photons from the emissive shapes of the Scene, in parallel, and builds the kd-tree of their hits.
+    pub fn new(scene: &Scene, n_photons: u32) -> Self {
+        let config = scene.get_config();
+        let n_workers = thread::available_parallelism()
+            .map(|n| n.get() as u32)
+            .unwrap_or(1);
+        let chunk_size = n_photons.div_ceil(n_workers).max(1);
+
+        let mut photons: Vec<Photon> = thread::scope(|s| {
+            let workers: Vec<_> = (0..n_photons)
+                .step_by(chunk_size as usize)
+                .map(|start| {
+                    s.spawn(move || {
+                        let mut sampler = config.sampler.create(n_photons, config.seed);
+                        let mut photons = vec![];
+                        for index in start..(start + chunk_size).min(n_photons) {
+                            // The photons are the samples of a pixel outside of the image
+                            sampler.start_sample(u32::MAX, u32::MAX, index);
+                            trace_photon(scene, sampler.as_mut(), n_photons, &mut photons);
+                        }
+                        photons
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        build_kd_tree(&mut photons);
+        PhotonMap { photons }
+    }
+
+    /// Estimates the light reflected toward the ray at the hit, from the density of the photons closer than radius.
+    /// The photons are only stored on surfaces, there is no estimate inside participating media:
+    /// the integrator follows the paths through the media to the next surface instead.
+    pub fn radiance(&self, ray: &Ray, hit: &HitRecord, radius: f64) -> Color {
+        let mut color = Color::new(0, 0, 0);
+        if hit.material.is_volumetric() {
+            return color;
+        }
+        self.for_each_near(
+            0,
+            self.photons.len(),
+            &hit.point,
+            radius * radius,
+            &mut |photon| {
+                let direction = -photon.direction;
+                let cos_theta = dot(&direction, &hit.normal).abs();
+                if cos_theta > 0.0 {
+                    color += photon.power * hit.material.eval(ray, hit, &direction) / cos_theta;
+                }
+            },
+        );
+        color / (PI * radius * radius)
+    }
+
+    /// Calls f on the photons of the subtree stored in [start, end[ whose squared distance to the point is below distance_squared.
+    fn for_each_near<F>(
+        &self,
+        start: usize,
+        end: usize,
+        point: &Vec3,
+        distance_squared: f64,
+        f: &mut F,
+    ) where
+        F: FnMut(&Photon),
+    {
+        if start >= end {
+            return;
+        }
+        let middle = (start + end) / 2;
+        let photon = &self.photons[middle];
+        if (photon.point - *point).len_squared() < distance_squared {
+            f(photon);
+        }
+
+        // Only visit the side of the splitting plane the point is not in if the sphere crosses the plane
+        let offset = point[photon.axis] - photon.point[photon.axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+        self.for_each_near(near.0, near.1, point, distance_squared, f);
+        if offset * offset < distance_squared {
+            self.for_each_near(far.0, far.1, point, distance_squared, f);
+        }
+    }
+}
+
+/// Traces a photon from a point sampled on the emissive shapes, storing it at each hit of a non specular surface after the first one.
+/// The photons go through the participating media without being stored in them.
+fn trace_photon(
+    scene: &Scene,
+    sampler: &mut dyn Sampler,
+    n_photons: u32,
+    photons: &mut Vec<Photon>,
+) {
+    let (hit, pdf) = match scene.sample_light_surface(sampler) {
+        Some(sample) => sample,
+        None => return,
+    };
+    // The direction is sampled with a cosine distribution, which cancels the cosine of the emitted light
+    let direction = (hit.normal + sample_unit_sphere(sampler.next_2d())).normalize();
+    if dot(&direction, &hit.normal) <= 0.0 {
+        return;
+    }
+    let emitted = hit.material.emit() * (PI / (pdf * n_photons as f64));
+    let mut throughput = Color::new(255, 255, 255);
+    let mut ray = Ray::new(hit.point, direction);
+    let config = scene.get_config();
+
+    for bounce in 0..config.max_ray_bounce {
+        let hit = match scene.get_photon_intersection(&ray) {
+            Some(hit) => hit,
+            None => return,
+        };
+        throughput *= hit.material.transmittance(&ray, &hit);
+        if bounce > 0 && !hit.material.is_specular() && !hit.material.is_volumetric() {
+            photons.push(Photon {
+                point: hit.point,
+                direction: ray.direction.normalize(),
+                power: emitted * throughput,
+                axis: 0,
+            });
+        }
+
+        let bouncing_ray = match hit.material.scatter(&ray, &hit, sampler) {
+            Some(bouncing_ray) => bouncing_ray,
+            None => return,
+        };
+        let pdf = hit.material.pdf(&ray, &hit, &bouncing_ray.direction);
+        if pdf > 0.0 {
+            throughput *= hit.material.eval(&ray, &hit, &bouncing_ray.direction) / pdf;
+        } else {
+            throughput *= hit.material.get_attenuation(&hit);
+        }
+        if throughput.is_black() {
+            return;
+        }
+
+        if let Some(depth) = config.russian_roulette_depth {
+            if bounce + 1 >= depth {
+                let survival = throughput.r.max(throughput.g).max(throughput.b).min(0.95);
+                if sampler.next_1d() >= survival {
+                    return;
+                }
+                throughput /= survival;
+            }
+        }
+
+        ray = bouncing_ray;
+    }
+}
+
+/// Reorders the photons into an implicit kd-tree, splitting each subtree along the axis where its photons are the most spread.
+fn build_kd_tree(photons: &mut [Photon]) {
+    if photons.len() <= 1 {
+        return;
+    }
+    let mut min = photons[0].point;
+    let mut max = photons[0].point;
+    for photon in photons.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(photon.point[axis]);
+            max[axis] = max[axis].max(photon.point[axis]);
+        }
+    }
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+
+    let middle = photons.len() / 2;
+    photons.select_nth_unstable_by(middle, |a, b| a.point[axis].total_cmp(&b.point[axis]));
+    photons[middle].axis = axis;
+    let (before, after) = photons.split_at_mut(middle);
+    build_kd_tree(before);
+    build_kd_tree(&mut after[1..]);
+}
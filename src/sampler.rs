@@ -0,0 +1,356 @@
+use std::f64::consts::PI;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::utils::Vec3;
+
+/// Source of the sample values used to render a pixel.
+///
+/// Every random decision of the renderer (position in the pixel, position on the lens, bounce directions...)
+/// consumes the next dimension of the current sample, so that well distributed samplers reduce the noise of all of them.
+pub trait Sampler {
+    /// Starts the sample number sample_index of the pixel at position (x,y), resetting the dimension counter.
+    fn start_sample(&mut self, x: u32, y: u32, sample_index: u32);
+
+    /// Returns the value of the next dimension of the current sample, between 0.0 and 1.0.
+    fn next_1d(&mut self) -> f64;
+
+    /// Returns the values of the next two dimensions of the current sample, between 0.0 and 1.0.
+    fn next_2d(&mut self) -> (f64, f64);
+}
+
+/// The different Samplers that can be used to render a Scene.
+///
+/// Random takes independent uniform random values.
+/// Stratified splits each dimension in as many strata as there are samples per pixel and jitters a value in each of them.
+/// Halton uses the Halton low discrepancy sequence, randomly shifted for each pixel.
+/// Sobol uses the (0,2) Sobol sequence for each pair of dimensions, randomly scrambled for each pixel.
+/// BlueNoise uses a low discrepancy sequence shifted by a blue noise pattern across the pixels,
+/// which makes the remaining noise of the image high frequency and less visible.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    Random,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// Creates a new Sampler of this kind, for pixels taking samples_per_pixel samples.
+    /// The values of each sample only depend on the seed, the pixel and the index of the sample,
+    /// so that renders are reproducible whatever the order in which the pixels are rendered.
+    pub fn create(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        let state = SamplerState::new(samples_per_pixel, seed);
+        match self {
+            SamplerKind::Random => Box::new(RandomSampler { state }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler { state }),
+            SamplerKind::Halton => Box::new(HaltonSampler { state }),
+            SamplerKind::Sobol => Box::new(SobolSampler { state }),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler { state }),
+        }
+    }
+}
+
+/// State shared by all the Samplers: the current pixel, sample and dimension.
+/// The random number generator is reseeded at the start of each sample.
+struct SamplerState {
+    rng: SmallRng,
+    seed: u64,
+    samples_per_pixel: u32,
+    x: u32,
+    y: u32,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SamplerState {
+    fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        SamplerState {
+            rng: seeded_rng(&[seed]),
+            seed,
+            samples_per_pixel: samples_per_pixel.max(1),
+            x: 0,
+            y: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.x = x;
+        self.y = y;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng = seeded_rng(&[self.seed, x as u64, y as u64, sample_index as u64]);
+    }
+
+    /// Returns the current dimension and moves to the next n dimensions.
+    fn take_dimensions(&mut self, n: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension += n;
+        dimension
+    }
+
+    /// Returns a hash of the current pixel and of the given dimension.
+    fn pixel_hash(&self, dimension: u32) -> u64 {
+        hash(&[self.seed, self.x as u64, self.y as u64, dimension as u64])
+    }
+
+    /// Returns the index of the current sample, shuffled differently for each pixel and dimension.
+    fn shuffled_index(&self, dimension: u32, n: u32) -> u32 {
+        permute(self.sample_index % n, n, self.pixel_hash(dimension) as u32)
+    }
+}
+
+struct RandomSampler {
+    state: SamplerState,
+}
+
+impl Sampler for RandomSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start_sample(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        self.state.rng.gen()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.state.rng.gen(), self.state.rng.gen())
+    }
+}
+
+struct StratifiedSampler {
+    state: SamplerState,
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start_sample(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let n = self.state.samples_per_pixel;
+        let dimension = self.state.take_dimensions(1);
+        let stratum = self.state.shuffled_index(dimension, n);
+        (stratum as f64 + self.state.rng.gen::<f64>()) / n as f64
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        // Use a square grid of strata large enough to hold all the samples of the pixel
+        let n = (self.state.samples_per_pixel as f64).sqrt().ceil() as u32;
+        let dimension = self.state.take_dimensions(2);
+        let stratum = self.state.shuffled_index(dimension, n * n);
+        (
+            ((stratum % n) as f64 + self.state.rng.gen::<f64>()) / n as f64,
+            ((stratum / n) as f64 + self.state.rng.gen::<f64>()) / n as f64,
+        )
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+struct HaltonSampler {
+    state: SamplerState,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start_sample(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let dimension = self.state.take_dimensions(1);
+        // Past the last prime of the table the sequence is too correlated to be useful
+        if dimension as usize >= PRIMES.len() {
+            return self.state.rng.gen();
+        }
+        let value = radical_inverse(PRIMES[dimension as usize], self.state.sample_index);
+        // Cranley-Patterson rotation to decorrelate the pixels
+        let offset = to_unit_float(self.state.pixel_hash(dimension) as u32);
+        (value + offset).fract()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+struct SobolSampler {
+    state: SamplerState,
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start_sample(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        self.next_2d().0
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let n = self.state.samples_per_pixel.next_power_of_two();
+        let dimension = self.state.take_dimensions(2);
+        let index = self.state.shuffled_index(dimension, n);
+        let scramble = self.state.pixel_hash(dimension);
+        sobol_02(index, scramble as u32, (scramble >> 32) as u32)
+    }
+}
+
+struct BlueNoiseSampler {
+    state: SamplerState,
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.state.start_sample(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let dimension = self.state.take_dimensions(1);
+        let golden_ratio = 0.618_033_988_749_895;
+        let value = self.state.sample_index as f64 * golden_ratio;
+        (value + self.blue_noise(dimension)).fract()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.take_dimensions(2);
+        // R2 sequence, the 2D generalization of the golden ratio sequence
+        let (a1, a2) = (0.754_877_666_246_692_7, 0.569_840_290_998_053_2);
+        let index = self.state.sample_index as f64;
+        (
+            (index * a1 + self.blue_noise(dimension)).fract(),
+            (index * a2 + self.blue_noise(dimension + 1)).fract(),
+        )
+    }
+}
+
+impl BlueNoiseSampler {
+    /// Returns the value of a blue noise pattern at the current pixel, randomly shifted for each dimension.
+    fn blue_noise(&self, dimension: u32) -> f64 {
+        // Interleaved gradient noise, whose neighbouring values are as different as possible
+        let (x, y) = (self.state.x as f64, self.state.y as f64);
+        let noise = (52.982_918_9 * (0.067_110_56 * x + 0.005_837_15 * y).fract()).fract();
+        let shift = to_unit_float(hash(&[self.state.seed, dimension as u64]) as u32);
+        (noise + shift).fract()
+    }
+}
+
+/// Returns the radical inverse of index in the given base, mirroring its digits around the decimal point.
+fn radical_inverse(base: u32, index: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut index = index;
+    let mut reversed = 0.0;
+    let mut factor = inv_base;
+    while index > 0 {
+        reversed += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inv_base;
+    }
+    reversed
+}
+
+/// Returns the point of the (0,2) Sobol sequence at index, XOR scrambled with the given values.
+fn sobol_02(index: u32, scramble_x: u32, scramble_y: u32) -> (f64, f64) {
+    let (mut x, mut y) = (scramble_x, scramble_y);
+    let (mut v_x, mut v_y) = (1u32 << 31, 1u32 << 31);
+    let mut index = index;
+    while index != 0 {
+        if index & 1 == 1 {
+            x ^= v_x;
+            y ^= v_y;
+        }
+        index >>= 1;
+        v_x >>= 1;
+        v_y ^= v_y >> 1;
+    }
+    (to_unit_float(x), to_unit_float(y))
+}
+
+/// Returns the element at position index of a random permutation of [0, length[ determined by seed.
+/// This is Andrew Kensler's hash based permutation, which does not need to store the permutation.
+fn permute(index: u32, length: u32, seed: u32) -> u32 {
+    if length <= 1 {
+        return 0;
+    }
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    let mut i = index;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % length
+}
+
+/// Hashes a list of values with the SplitMix64 finalizer.
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    let mut h = 0x9e37_79b9_7f4a_7c15u64;
+    for value in values {
+        h ^= value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+    }
+    h
+}
+
+/// Returns a random number generator whose seed is the hash of the given values.
+pub(crate) fn seeded_rng(values: &[u64]) -> SmallRng {
+    SmallRng::seed_from_u64(hash(values))
+}
+
+fn to_unit_float(value: u32) -> f64 {
+    value as f64 / 4_294_967_296.0
+}
+
+/// Maps a 2D sample to a uniformly distributed point on the unit sphere.
+pub fn sample_unit_sphere((u, v): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Maps a 2D sample to a uniformly distributed point on the unit disk, using the concentric mapping.
+pub fn sample_unit_disk((u, v): (f64, f64)) -> (f64, f64) {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
@@ -1,18 +1,31 @@
 use std::sync::Arc;
 
+use rand::rngs::SmallRng;
+
 use crate::bvh::BVH;
 use crate::camera::Camera;
+use crate::integrator::{power_heuristic, Integrator, IntegratorKind, Splat};
+use crate::light::Light;
+use crate::medium::{medium_transmittance, sample_medium_hit, Medium};
+use crate::photon_map::PhotonMap;
 use crate::ray::Ray;
+use crate::sampler::{seeded_rng, Sampler};
 use crate::shapes::collide::{Collide, HitRecord};
-use crate::utils::Color;
+use crate::utils::{Color, Vec3};
 use crate::Config;
 
 type ArcCollide = Arc<dyn Collide + Send + Sync>;
+type BoxLight = Box<dyn Light + Send + Sync>;
 
 /// Use this struct for building a Scene.
 ///
 /// # Example
 /// ```
+/// # use raytracer::material::Diffuse;
+/// # use raytracer::scene::SceneBuilder;
+/// # use raytracer::shapes::sphere::Sphere;
+/// # use raytracer::utils::{Color, Vec3};
+/// # let config = raytracer::Config::default();
 /// let mut scene_builder = SceneBuilder::new(config);
 ///
 /// scene_builder.add_shape(
@@ -24,17 +37,23 @@ type ArcCollide = Arc<dyn Collide + Send + Sync>;
 /// ```
 pub struct SceneBuilder {
     config: Config,
+    rng: SmallRng,
     skybox_color: Color,
+    medium: Option<Arc<Medium>>,
     shapes: Vec<ArcCollide>,
+    punctual_lights: Vec<BoxLight>,
 }
 
 impl SceneBuilder {
     /// Creates a new SceneBuilder.
     pub fn new(config: Config) -> Self {
         SceneBuilder {
+            rng: seeded_rng(&[config.seed]),
             config,
             skybox_color: Color::new(255, 255, 255),
+            medium: None,
             shapes: vec![],
+            punctual_lights: vec![],
         }
     }
 
@@ -44,6 +63,19 @@ impl SceneBuilder {
         self
     }
 
+    /// Fills the whole scene with the medium, like a fog.
+    /// The rays leaving the shapes are then scattered at a random distance instead of reaching the skybox.
+    pub fn set_medium(mut self, medium: Medium) -> Self {
+        self.medium = Some(Arc::new(medium));
+        self
+    }
+
+    /// Returns a random number generator seeded with the seed of the config.
+    /// Use it to build procedural scenes that are identical for each run with the same seed.
+    pub fn get_rng(&mut self) -> &mut SmallRng {
+        &mut self.rng
+    }
+
     /// Adds the shape to the SceneBuilder.
     pub fn add_shape<T>(&mut self, shape: T)
     where
@@ -52,15 +84,47 @@ impl SceneBuilder {
         self.shapes.push(Arc::new(shape));
     }
 
+    /// Adds a point, spot or directional light to the SceneBuilder.
+    pub fn add_light<T>(&mut self, light: T)
+    where
+        T: Light + Send + Sync + 'static,
+    {
+        self.punctual_lights.push(Box::new(light));
+    }
+
     /// Computes the Bounding Volume Hierarchy (BVH) for the current SceneBuilder and use it to create a Scene that can be rendered.
+    /// The shapes with an emissive material are collected as the lights of the Scene.
+    /// With the photon mapping integrator, the photons are also traced from the lights and stored in the Scene.
+    ///
+    /// # Panics
+    /// Panics if the radius of the photon mapping integrator is not positive.
     pub fn to_scene(mut self) -> Scene {
+        if let IntegratorKind::PhotonMapping { radius, .. } = self.config.integrator {
+            if radius.is_nan() || radius <= 0.0 {
+                panic!("The radius of the photon mapping integrator should be positive as the photons are gathered in a disk of this radius");
+            }
+        }
+        let lights = self
+            .shapes
+            .iter()
+            .flat_map(|shape| shape.clone().get_lights())
+            .collect();
         let n = self.shapes.len();
         let bvh = BVH::new(&mut self.shapes, 0, n);
-        Scene {
+        let mut scene = Scene {
+            integrator: self.config.integrator.create(),
             config: self.config,
             skybox_color: self.skybox_color,
+            medium: self.medium,
             bvh,
+            lights,
+            punctual_lights: self.punctual_lights,
+            photon_map: None,
+        };
+        if let IntegratorKind::PhotonMapping { photons, .. } = scene.config.integrator {
+            scene.photon_map = Some(PhotonMap::new(&scene, photons));
         }
+        scene
     }
 }
 
@@ -69,7 +133,12 @@ impl SceneBuilder {
 pub struct Scene {
     config: Config,
     skybox_color: Color,
+    medium: Option<Arc<Medium>>,
     bvh: BVH,
+    lights: Vec<ArcCollide>,
+    punctual_lights: Vec<BoxLight>,
+    integrator: Box<dyn Integrator + Send + Sync>,
+    photon_map: Option<PhotonMap>,
 }
 
 impl Scene {
@@ -78,59 +147,224 @@ impl Scene {
         &self.config
     }
 
-    fn get_ray_color(&self, camera: &Camera, ray: &Ray, depth: u32) -> Color {
-        if depth == 0 {
-            return Color::new(0, 0, 0);
-        }
+    /// Returns the skybox color of the Scene.
+    pub fn get_skybox_color(&self) -> Color {
+        self.skybox_color
+    }
 
-        // Get the intersection that is the closest to the camera
-        let mut min_hit_record: Option<HitRecord> = None;
-        let max_t = camera.get_far_clip_plane();
-        let min_t = if depth == self.config.max_ray_bounce {
+    /// Returns the closest intersection of the ray with the shapes of the Scene.
+    /// Rays coming from the camera start at its near clip plane, the other ones slightly after their origin to avoid hitting the shape they leave.
+    pub(crate) fn get_intersection(
+        &self,
+        camera: &Camera,
+        ray: &Ray,
+        from_camera: bool,
+    ) -> Option<HitRecord> {
+        let min_t = if from_camera {
             camera.get_near_clip_plane()
         } else {
             0.0001
         };
+        self.intersect(ray, min_t, camera.get_far_clip_plane())
+    }
 
-        if let Some(hit_record) = self.bvh.get_intersection(&ray, min_t, max_t) {
-            min_hit_record = Some(hit_record);
+    /// Returns the closest intersection of the ray with the shapes of the Scene between t_min and t_max,
+    /// unless it is scattered before by the medium filling the Scene.
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let hit = self.bvh.get_intersection(ray, t_min, t_max);
+        match &self.medium {
+            Some(medium) => {
+                let t_max = hit.as_ref().map_or(t_max, |hit| hit.t);
+                sample_medium_hit(medium, ray, t_min, t_max).or(hit)
+            }
+            None => hit,
         }
+    }
 
-        // If we found a shape intersecting with the ray render the shape
-        if let Some(hit) = min_hit_record {
-            let emited = hit.material.emit();
-            if let Some(bouncing_ray) = hit.material.scatter(ray, &hit) {
-                return hit.material.get_attenuation()
-                    * self.get_ray_color(camera, &bouncing_ray, depth - 1);
-            } else {
-                return emited;
+    /// Returns the closest intersection of a ray traced from a light with the shapes of the Scene.
+    pub(crate) fn get_photon_intersection(&self, ray: &Ray) -> Option<HitRecord> {
+        self.intersect(ray, 0.0001, f64::INFINITY)
+    }
+
+    /// Returns the fraction of the light going through the Scene along the ray between t_min and t_max,
+    /// through the participating media and the medium filling the Scene.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let transmittance = self.bvh.transmittance(ray, t_min, t_max);
+        match &self.medium {
+            Some(medium) if transmittance > 0.0 => {
+                transmittance * medium_transmittance(medium, ray, t_min, t_max)
             }
-        // Else we render the skybox
-        } else {
-            self.skybox_color
+            _ => transmittance,
         }
     }
 
-    /// Returns the computed color for the pixel at position (x,y) through the Camera.
-    pub fn get_pixel_color(&self, camera: &Camera, x: u32, y: u32) -> Color {
-        if self.config.anti_aliasing.is_none() {
-            let u = x as f64 / (self.config.width as f64 - 1.0);
-            let v = (self.config.height as f64 - y as f64) / (self.config.height as f64); // y axis goes up
-            return self.get_ray_color(&camera, &camera.get_ray(u, v), self.config.max_ray_bounce);
+    /// Returns true if the Scene has emissive shapes.
+    pub(crate) fn has_lights(&self) -> bool {
+        !self.lights.is_empty()
+    }
+
+    /// Estimates the light directly received at the hit from one emissive shape of the Scene, picked uniformly.
+    /// If mis is true, the sample is weighted against the sampling of the material with multiple importance sampling.
+    pub(crate) fn sample_light(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+        mis: bool,
+    ) -> Color {
+        let black = Color::new(0, 0, 0);
+        let n_lights = self.lights.len();
+        let index = ((sampler.next_1d() * n_lights as f64) as usize).min(n_lights - 1);
+        let (point, pdf) = match self.lights[index].sample_point(&hit.point, sampler.next_2d()) {
+            Some(sample) => sample,
+            None => return black,
+        };
+        let light_pdf = pdf / n_lights as f64;
+
+        let direction = point - hit.point;
+        let bsdf = hit.material.eval(ray, hit, &direction);
+        if bsdf.is_black() || light_pdf <= 0.0 {
+            return black;
+        }
+
+        // The shadow ray reaches the sampled point at t = 1, the light is attenuated by everything before
+        let shadow_ray = Ray::new(hit.point, direction);
+        let transmittance = self.transmittance(&shadow_ray, 0.0001, 1.0 - 1e-3);
+        if transmittance <= 0.0 {
+            return black;
         }
-        let n_samples_root = self.config.anti_aliasing.unwrap();
-        let mut color_sum = Color::new(0, 0, 0);
-        for offset_u in 0..n_samples_root {
-            for offset_v in 0..n_samples_root {
-                let x = x as f64 + offset_u as f64 / (n_samples_root - 1) as f64;
-                let y = y as f64 + offset_v as f64 / (n_samples_root - 1) as f64;
-                let u = x as f64 / (self.config.width as f64 - 1.0);
-                let v = (self.config.height as f64 - y) / (self.config.height as f64); // y axis goes up
-                let ray = camera.get_ray(u, v);
-                let color = self.get_ray_color(&camera, &ray, self.config.max_ray_bounce);
-                color_sum += color;
+        match self.lights[index].get_intersection(&shadow_ray, 1.0 - 1e-3, 1.0 + 1e-3) {
+            Some(light_hit) => {
+                // The direction could have been sampled toward any of the lights, like the material side of the weight counts it
+                let weight = if mis {
+                    power_heuristic(
+                        self.light_pdf(&hit.point, &direction),
+                        hit.material.pdf(ray, hit, &direction),
+                    )
+                } else {
+                    1.0
+                };
+                light_hit.material.emit() * bsdf * (transmittance * weight / light_pdf)
+            }
+            None => black,
+        }
+    }
+
+    /// Computes the light directly received at the hit from all the point, spot and directional lights of the Scene.
+    pub(crate) fn sample_punctual_lights(&self, ray: &Ray, hit: &HitRecord) -> Color {
+        let mut color = Color::new(0, 0, 0);
+        for light in &self.punctual_lights {
+            let sample = match light.illuminate(&hit.point) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            let bsdf = hit.material.eval(ray, hit, &sample.direction);
+            if bsdf.is_black() {
+                continue;
             }
+            let shadow_ray = Ray::new(hit.point, sample.direction);
+            let t_max = sample.distance * (1.0 - 1e-4);
+            color += sample.radiance * bsdf * self.transmittance(&shadow_ray, 0.0001, t_max);
+        }
+        color
+    }
+
+    /// Returns the density, in solid angle, with which sample_light samples the direction from origin.
+    pub(crate) fn light_pdf(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let sum: f64 = self
+            .lights
+            .iter()
+            .map(|light| light.pdf_value(origin, direction))
+            .sum();
+        sum / self.lights.len() as f64
+    }
+
+    /// Estimates the indirect light reflected toward the ray at the hit from the photons stored closer than radius.
+    /// Returns black if no photons were traced for the Scene.
+    pub(crate) fn gather_photons(&self, ray: &Ray, hit: &HitRecord, radius: f64) -> Color {
+        match &self.photon_map {
+            Some(photon_map) => photon_map.radiance(ray, hit, radius),
+            None => Color::new(0, 0, 0),
         }
-        color_sum / (n_samples_root * n_samples_root) as f64
+    }
+
+    /// Returns the fraction of the light going from the point a to the point b, zero if a surface blocks the segment.
+    pub(crate) fn transmittance_between(&self, a: &Vec3, b: &Vec3) -> f64 {
+        let to_b = *b - *a;
+        let distance = to_b.len();
+        let ray = Ray::new(*a, to_b / distance);
+        self.transmittance(&ray, 0.0001, distance * (1.0 - 1e-4))
+    }
+
+    /// Picks an emissive shape of the Scene uniformly and samples a point uniformly on its surface.
+    /// Returns the HitRecord of the point and the probability density of having sampled it, per unit area.
+    pub(crate) fn sample_light_surface(
+        &self,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(HitRecord, f64)> {
+        let n_lights = self.lights.len();
+        if n_lights == 0 {
+            return None;
+        }
+        let index = ((sampler.next_1d() * n_lights as f64) as usize).min(n_lights - 1);
+        let light = &self.lights[index];
+        let hit = light.sample_surface(sampler.next_2d())?;
+        let area = light.get_area();
+        if area <= 0.0 {
+            return None;
+        }
+        Some((hit, 1.0 / (n_lights as f64 * area)))
+    }
+
+    /// Returns the density per unit area with which sample_light_surface samples the point of an emissive shape,
+    /// seen from origin.
+    pub(crate) fn light_surface_pdf(&self, origin: &Vec3, point: &Vec3) -> f64 {
+        // Find the emissive shape the point belongs to by intersecting it along the segment
+        let ray = Ray::new(*origin, *point - *origin);
+        self.lights
+            .iter()
+            .find(|light| {
+                light
+                    .get_intersection(&ray, 0.0, f64::INFINITY)
+                    .is_some_and(|hit| (hit.t - 1.0).abs() < 1e-6)
+            })
+            .map_or(0.0, |light| {
+                1.0 / (self.lights.len() as f64 * light.get_area())
+            })
+    }
+
+    /// Returns the color computed through the point (x,y) of the image, in pixels.
+    /// The light brought to other positions of the image by the integrator is pushed in splats.
+    pub fn get_image_point_color(
+        &self,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        x: f64,
+        y: f64,
+        splats: &mut Vec<Splat>,
+    ) -> Color {
+        let u = x / self.config.width as f64;
+        let v = (self.config.height as f64 - y) / self.config.height as f64; // y axis goes up
+        let ray = camera.get_ray(u, v, sampler);
+        self.integrator
+            .get_ray_color(self, camera, sampler, ray, splats)
+    }
+
+    /// Takes the sample number sample_index of the pixel at position (x,y).
+    /// Returns the position of the sample in the image, in pixels, and the color computed through it.
+    pub fn get_pixel_sample(
+        &self,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        x: u32,
+        y: u32,
+        sample_index: u32,
+        splats: &mut Vec<Splat>,
+    ) -> (f64, f64, Color) {
+        sampler.start_sample(x, y, sample_index);
+        let (offset_x, offset_y) = sampler.next_2d();
+        let (sample_x, sample_y) = (x as f64 + offset_x, y as f64 + offset_y);
+        let color = self.get_image_point_color(camera, sampler, sample_x, sample_y, splats);
+        (sample_x, sample_y, color)
     }
 }
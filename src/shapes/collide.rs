@@ -3,21 +3,66 @@ use std::sync::Arc;
 use crate::bvh::AABB;
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::utils::Vec3;
+use crate::utils::{Base, Vec3};
 
 /// Implement this trait for all objects that can be rendered in the Scene
 pub trait Collide {
     fn get_intersection(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn get_bounding_box(&self) -> Option<AABB>;
+
+    /// Returns the fraction of the light that goes through the object along the ray, between t_min and t_max.
+    /// Surfaces block all the light when the ray hits them, participating media let part of it through.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.get_intersection(ray, t_min, t_max) {
+            Some(_) => 0.0,
+            None => 1.0,
+        }
+    }
+
+    /// Returns the parts of the object that emit light, so that the Scene can sample them directly.
+    fn get_lights(self: Arc<Self>) -> Vec<Arc<dyn Collide + Send + Sync>> {
+        vec![]
+    }
+
+    /// Samples a point of the object seen from origin, using the 2D sample.
+    /// Returns the sampled point and the probability density of the direction from origin to this point, in solid angle.
+    /// Objects that can be used as lights must implement this method.
+    fn sample_point(&self, _origin: &Vec3, _sample: (f64, f64)) -> Option<(Vec3, f64)> {
+        None
+    }
+
+    /// Returns the probability density, in solid angle, that sample_point samples the direction from origin.
+    /// The density is zero if the direction doesn't reach the object.
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Uniformly samples a point on the surface of the object, using the 2D sample.
+    /// The returned HitRecord holds the point, the normal on the side that emits light and the material of the object.
+    fn sample_surface(&self, _sample: (f64, f64)) -> Option<HitRecord> {
+        None
+    }
+
+    /// Returns the area of the surface of the object.
+    fn get_area(&self) -> f64 {
+        0.0
+    }
 }
 
 /// Informations about the hit of an object
+#[derive(Clone)]
 pub struct HitRecord {
     pub point: Vec3,
     pub normal: Vec3,
     pub t: f64,
     pub front_face: bool,
     pub material: Arc<dyn Material>,
+    /// Texture coordinates of the point, each between 0 and 1
+    pub u: f64,
+    pub v: f64,
+    /// Derivatives of the point with respect to the texture coordinates, tangent to the surface
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
 }
 
 impl HitRecord {
@@ -33,12 +78,32 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        // Any tangent frame, until the shape sets the one of its parameterization
+        let base = Base::from_w(outward_normal);
         HitRecord {
             point,
             normal,
             t,
             front_face,
             material,
+            u: 0.0,
+            v: 0.0,
+            dpdu: base.u(),
+            dpdv: base.v(),
         }
     }
+
+    /// Sets the texture coordinates of the point, used to sample the textures of the material.
+    pub fn set_uv(mut self, u: f64, v: f64) -> Self {
+        self.u = u;
+        self.v = v;
+        self
+    }
+
+    /// Sets the derivatives of the point with respect to the texture coordinates u and v.
+    pub fn set_tangents(mut self, dpdu: Vec3, dpdv: Vec3) -> Self {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self
+    }
 }
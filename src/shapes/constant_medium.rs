@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use crate::bvh::AABB;
+use crate::medium::{medium_transmittance, sample_medium_hit, Medium};
+use crate::ray::Ray;
+use crate::shapes::collide::{Collide, HitRecord};
+
+/// A shape filled with a homogeneous Medium, like a cloud of smoke.
+///
+/// The boundary must be a convex shape that can be hit from the inside, like a Sphere.
+/// The boundary itself isn't rendered: put the ConstantMedium inside a Dielectric shape to render murky water or tinted glass.
+///
+/// # Example
+/// ```
+/// # use raytracer::medium::Medium;
+/// # use raytracer::shapes::constant_medium::ConstantMedium;
+/// # use raytracer::shapes::sphere::Sphere;
+/// # use raytracer::utils::Vec3;
+/// let smoke = ConstantMedium::new(
+///     Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0),
+///     Medium::new(0.2, 1.5),
+/// );
+/// ```
+pub struct ConstantMedium {
+    boundary: Box<dyn Collide + Send + Sync>,
+    medium: Arc<Medium>,
+}
+
+impl ConstantMedium {
+    /// Creates a new ConstantMedium filling the boundary with the medium.
+    pub fn new<T>(boundary: T, medium: Medium) -> Self
+    where
+        T: Collide + Send + Sync + 'static,
+    {
+        ConstantMedium {
+            boundary: Box::new(boundary),
+            medium: Arc::new(medium),
+        }
+    }
+}
+
+impl Collide for ConstantMedium {
+    fn get_intersection(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t_start, t_end) = self.get_inside_interval(ray, t_min, t_max)?;
+        sample_medium_hit(&self.medium, ray, t_start, t_end)
+    }
+
+    fn get_bounding_box(&self) -> Option<AABB> {
+        self.boundary.get_bounding_box()
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.get_inside_interval(ray, t_min, t_max) {
+            Some((t_start, t_end)) => medium_transmittance(&self.medium, ray, t_start, t_end),
+            None => 1.0,
+        }
+    }
+}
+
+impl ConstantMedium {
+    /// Returns the interval of t, clipped to [t_min, t_max], during which the ray is inside the boundary.
+    fn get_inside_interval(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        // The ray may start inside the boundary
+        let entry = self
+            .boundary
+            .get_intersection(ray, f64::NEG_INFINITY, f64::INFINITY)?;
+        let exit = self
+            .boundary
+            .get_intersection(ray, entry.t + 0.0001, f64::INFINITY)?;
+        let t_start = entry.t.max(t_min);
+        let t_end = exit.t.min(t_max);
+        if t_start >= t_end {
+            return None;
+        }
+        Some((t_start, t_end))
+    }
+}
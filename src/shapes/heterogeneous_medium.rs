@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::bvh::AABB;
+use crate::medium::{medium_hit, ray_rng, Medium};
+use crate::ray::Ray;
+use crate::shapes::collide::{Collide, HitRecord};
+use crate::utils::Vec3;
+use crate::voxel_grid::VoxelGrid;
+
+/// A box filled with a Medium whose density varies, like a cloud or an explosion.
+///
+/// The absorption and scattering coefficients of the Medium are multiplied by the density of the VoxelGrid stretched over the box.
+/// The points where the rays are scattered are sampled with delta tracking,
+/// and the light going through the volume toward the lights is estimated with ratio tracking.
+///
+/// # Example
+/// ```
+/// # use raytracer::medium::Medium;
+/// # use raytracer::shapes::heterogeneous_medium::HeterogeneousMedium;
+/// # use raytracer::utils::Vec3;
+/// # use raytracer::voxel_grid::VoxelGrid;
+/// let cloud = HeterogeneousMedium::new(
+///     Vec3::new(-2.0, 1.0, -2.0),
+///     Vec3::new(2.0, 3.0, 2.0),
+///     VoxelGrid::from_noise(64, 4.0, 7),
+///     Medium::new(0.1, 4.0).set_anisotropy(0.5),
+/// );
+/// ```
+pub struct HeterogeneousMedium {
+    aabb: AABB,
+    grid: VoxelGrid,
+    medium: Arc<Medium>,
+}
+
+impl HeterogeneousMedium {
+    /// Creates a new HeterogeneousMedium filling the box between the corners min and max.
+    pub fn new(min: Vec3, max: Vec3, grid: VoxelGrid, medium: Medium) -> Self {
+        if (0..3).any(|axis| min[axis] >= max[axis]) {
+            panic!("The min corner of a HeterogeneousMedium must be lower than its max corner along each axis");
+        }
+        HeterogeneousMedium {
+            aabb: AABB::new(min, max),
+            grid,
+            medium: Arc::new(medium),
+        }
+    }
+
+    /// Returns the density of the grid at the point, in world coordinates.
+    fn get_density(&self, point: &Vec3) -> f64 {
+        let (min, max) = (self.aabb.get_min(), self.aabb.get_max());
+        let local = |axis: usize| (point[axis] - min[axis]) / (max[axis] - min[axis]);
+        self.grid.get_density(local(0), local(1), local(2))
+    }
+
+    /// Returns the extinction of the Medium at its highest density, which bounds the extinction everywhere in the box.
+    fn majorant(&self) -> f64 {
+        self.grid.get_max_density() * self.medium.extinction()
+    }
+
+    /// Returns the values used to salt the random numbers of the rays going through the volume.
+    /// They identify its box, grid and Medium, so that overlapping volumes stop the rays independently.
+    fn salt(&self, t_min: f64) -> [u64; 10] {
+        let [min_x, min_y, min_z] = self.aabb.get_min().to_bits();
+        let [max_x, max_y, max_z] = self.aabb.get_max().to_bits();
+        let [absorption, scattering] = self.medium.get_salt();
+        [
+            t_min.to_bits(),
+            min_x,
+            min_y,
+            min_z,
+            max_x,
+            max_y,
+            max_z,
+            self.grid.get_key(),
+            absorption,
+            scattering,
+        ]
+    }
+}
+
+impl Collide for HeterogeneousMedium {
+    /// Delta tracking: tentative collisions are sampled as if the whole box had the highest density,
+    /// and each of them is a real collision with a probability equal to the ratio of the density at this point to the highest one.
+    fn get_intersection(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t_start, t_end) = self.aabb.get_hit_interval(ray, t_min, t_max)?;
+        let majorant = self.majorant();
+        let length = ray.direction.len();
+        if majorant <= 0.0 || length <= 0.0 {
+            return None;
+        }
+
+        let mut rng = ray_rng(ray, &self.salt(t_min));
+        let mut t = t_start;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (majorant * length);
+            if t >= t_end {
+                return None;
+            }
+            let density = self.get_density(&ray.at(t));
+            if rng.gen::<f64>() * self.grid.get_max_density() < density {
+                return Some(medium_hit(&self.medium, ray, t));
+            }
+        }
+    }
+
+    fn get_bounding_box(&self) -> Option<AABB> {
+        Some(self.aabb)
+    }
+
+    /// Ratio tracking: the transmittance is multiplied at each tentative collision by the probability that it is not a real one.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let (t_start, t_end) = match self.aabb.get_hit_interval(ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return 1.0,
+        };
+        let majorant = self.majorant();
+        let length = ray.direction.len();
+        if majorant <= 0.0 || length <= 0.0 {
+            return 1.0;
+        }
+
+        let mut rng = ray_rng(ray, &self.salt(t_min));
+        let mut transmittance = 1.0;
+        let mut t = t_start;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (majorant * length);
+            if t >= t_end {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.get_density(&ray.at(t)) / self.grid.get_max_density();
+
+            // Russian roulette, to stop tracking the rays that almost don't carry light anymore
+            if transmittance < 0.1 {
+                if rng.gen::<f64>() >= 0.5 {
+                    return 0.0;
+                }
+                transmittance *= 2.0;
+            }
+        }
+    }
+}
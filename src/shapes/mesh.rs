@@ -13,6 +13,7 @@ use crate::{
 /// Represents a mesh loaded from an STL file.
 pub struct STLMesh {
     bvh: BVH,
+    lights: Vec<Arc<dyn Collide + Send + Sync>>,
 }
 
 impl Collide for STLMesh {
@@ -23,6 +24,10 @@ impl Collide for STLMesh {
     fn get_bounding_box(&self) -> Option<AABB> {
         self.bvh.get_bounding_box()
     }
+
+    fn get_lights(self: Arc<Self>) -> Vec<Arc<dyn Collide + Send + Sync>> {
+        self.lights.clone()
+    }
 }
 
 impl STLMesh {
@@ -65,9 +70,13 @@ impl STLMesh {
             t.set_material(arc_material.clone());
             triangles.push(Arc::new(t));
         }
+        let lights = triangles
+            .iter()
+            .flat_map(|triangle| triangle.clone().get_lights())
+            .collect();
         let n = triangles.len();
         let bvh = BVH::new(&mut triangles, 0, n);
 
-        STLMesh { bvh }
+        STLMesh { bvh, lights }
     }
 }
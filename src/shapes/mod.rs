@@ -1,4 +1,6 @@
 pub mod collide;
+pub mod constant_medium;
+pub mod heterogeneous_medium;
 pub mod mesh;
 pub mod sphere;
 pub mod triangle;
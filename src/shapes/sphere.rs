@@ -1,13 +1,15 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 use crate::bvh::AABB;
 use crate::material::Diffuse;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::{sample_unit_sphere, seeded_rng};
 use crate::shapes::collide::Collide;
 use crate::shapes::collide::HitRecord;
 use crate::utils::Color;
-use crate::utils::{dot, Vec3};
+use crate::utils::{dot, Base, Vec3};
 
 type ArcMaterial = Arc<dyn Material + Send + Sync>;
 
@@ -20,11 +22,14 @@ pub struct Sphere {
 
 impl Sphere {
     /// Creates a new Sphere
+    /// Its default material is diffuse, with a random color derived from the position and radius of the Sphere.
     pub fn new(center: Vec3, radius: f64) -> Self {
+        let [x, y, z] = center.to_bits();
+        let mut rng = seeded_rng(&[x, y, z, radius.to_bits()]);
         Sphere {
             center,
             radius,
-            material: Arc::new(Diffuse::new(Color::random())),
+            material: Arc::new(Diffuse::new(Color::random(&mut rng))),
         }
     }
 
@@ -36,6 +41,27 @@ impl Sphere {
         self.material = Arc::new(material);
         self
     }
+
+    /// Derivatives of the point of the Sphere of the given outward normal, with respect to the texture coordinates of sphere_uv.
+    /// At the poles, where u is undefined, dpdu is null.
+    fn tangents(&self, normal: &Vec3) -> (Vec3, Vec3) {
+        let dpdu = Vec3::new(normal.z, 0.0, -normal.x) * (2.0 * PI * self.radius);
+        let sin_theta = (normal.x * normal.x + normal.z * normal.z)
+            .sqrt()
+            .max(1e-12);
+        let dpdv = Vec3::new(
+            -normal.y * normal.x / sin_theta,
+            sin_theta,
+            -normal.y * normal.z / sin_theta,
+        ) * (PI * self.radius);
+        (dpdu, dpdv)
+    }
+
+    /// Density of the directions uniformly sampled in the cone that sees the Sphere from a point at the given squared distance of its center.
+    fn cone_pdf(&self, distance_squared: f64) -> f64 {
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
 }
 
 impl Collide for Sphere {
@@ -65,17 +91,92 @@ impl Collide for Sphere {
         let point = ray.at(t);
         let outward_normal = (point - self.center).normalize();
         let front_face = dot(&ray.direction, &outward_normal) < 0.0;
-        Some(HitRecord::new(
-            point,
-            outward_normal,
-            t,
-            front_face,
-            self.material.clone(),
-        ))
+        let (u, v) = sphere_uv(&outward_normal);
+        let (dpdu, dpdv) = self.tangents(&outward_normal);
+        Some(
+            HitRecord::new(point, outward_normal, t, front_face, self.material.clone())
+                .set_uv(u, v)
+                .set_tangents(dpdu, dpdv),
+        )
     }
 
     fn get_bounding_box(&self) -> Option<AABB> {
         let radius3 = Vec3::new(self.radius, self.radius, self.radius);
         Some(AABB::new(self.center - radius3, self.center + radius3))
     }
+
+    fn get_lights(self: Arc<Self>) -> Vec<Arc<dyn Collide + Send + Sync>> {
+        if self.material.is_emissive() {
+            vec![self]
+        } else {
+            vec![]
+        }
+    }
+
+    fn sample_point(&self, origin: &Vec3, (u, v): (f64, f64)) -> Option<(Vec3, f64)> {
+        // Uniformly sample the cone of directions from origin that see the Sphere
+        let to_center = self.center - *origin;
+        let distance_squared = to_center.len_squared();
+        if distance_squared <= self.radius * self.radius {
+            return None;
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let cos_theta = 1.0 - u * (1.0 - cos_theta_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        let base = Base::from_w(to_center);
+        let direction = base.to_world(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        // Distance to the closest intersection with the Sphere along the sampled direction
+        let distance = distance_squared.sqrt();
+        let t = distance * cos_theta
+            - (self.radius * self.radius - distance_squared * sin_theta * sin_theta)
+                .max(0.0)
+                .sqrt();
+        Some((*origin + direction * t, self.cone_pdf(distance_squared)))
+    }
+
+    fn sample_surface(&self, sample: (f64, f64)) -> Option<HitRecord> {
+        let normal = sample_unit_sphere(sample);
+        let (u, v) = sphere_uv(&normal);
+        let (dpdu, dpdv) = self.tangents(&normal);
+        Some(
+            HitRecord::new(
+                self.center + normal * self.radius,
+                normal,
+                0.0,
+                true,
+                self.material.clone(),
+            )
+            .set_uv(u, v)
+            .set_tangents(dpdu, dpdv),
+        )
+    }
+
+    fn get_area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let distance_squared = (self.center - *origin).len_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 0.0;
+        }
+        let ray = Ray::new(*origin, *direction);
+        match self.get_intersection(&ray, 0.0, f64::INFINITY) {
+            Some(_) => self.cone_pdf(distance_squared),
+            None => 0.0,
+        }
+    }
+}
+
+/// Texture coordinates of the point of the unit sphere: u goes around the y axis, v from the bottom pole to the top one.
+fn sphere_uv(point: &Vec3) -> (f64, f64) {
+    let phi = (-point.z).atan2(point.x) + PI;
+    let theta = (-point.y).clamp(-1.0, 1.0).acos();
+    (phi / (2.0 * PI), theta / PI)
 }
@@ -4,8 +4,9 @@ use crate::{
     bvh::AABB,
     material::{Diffuse, Material},
     ray::Ray,
+    sampler::seeded_rng,
     shapes::collide::{Collide, HitRecord},
-    utils::{cross, dot, Color, Vec3},
+    utils::{cross, dot, Base, Color, Vec3},
 };
 
 type ArcMaterial = Arc<dyn Material + Send + Sync>;
@@ -16,6 +17,7 @@ pub struct Triangle {
     vertices: [Vec3; 3],
     normal: Vec3,
     material: ArcMaterial,
+    uvs: [(f64, f64); 3],
 }
 
 impl Collide for Triangle {
@@ -33,7 +35,7 @@ impl Collide for Triangle {
         let inv_det = 1.0 / det;
         let t_vec = ray.origin - self.vertices[0];
         let u = dot(&t_vec, &p_vec) * inv_det;
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
 
@@ -49,13 +51,13 @@ impl Collide for Triangle {
         }
         let p = ray.origin + ray.direction * t;
 
-        Some(HitRecord::new(
-            p,
-            self.normal,
-            t,
-            true,
-            self.material.clone(),
-        ))
+        let (hit_u, hit_v) = self.interpolate_uv(u, v);
+        let (dpdu, dpdv) = self.tangents();
+        Some(
+            HitRecord::new(p, self.normal, t, true, self.material.clone())
+                .set_uv(hit_u, hit_v)
+                .set_tangents(dpdu, dpdv),
+        )
     }
 
     fn get_bounding_box(&self) -> Option<AABB> {
@@ -83,15 +85,68 @@ impl Collide for Triangle {
         );
         Some(AABB::new(min, max))
     }
+
+    fn get_lights(self: Arc<Self>) -> Vec<Arc<dyn Collide + Send + Sync>> {
+        if self.material.is_emissive() {
+            vec![self]
+        } else {
+            vec![]
+        }
+    }
+
+    fn sample_point(&self, origin: &Vec3, (u, v): (f64, f64)) -> Option<(Vec3, f64)> {
+        // Uniformly sample the area of the Triangle
+        let sqrt_u = u.sqrt();
+        let point = self.vertices[0] * (1.0 - sqrt_u)
+            + self.vertices[1] * (sqrt_u * (1.0 - v))
+            + self.vertices[2] * (sqrt_u * v);
+
+        self.solid_angle_pdf(origin, &point).map(|pdf| (point, pdf))
+    }
+
+    fn sample_surface(&self, (u, v): (f64, f64)) -> Option<HitRecord> {
+        let sqrt_u = u.sqrt();
+        let point = self.vertices[0] * (1.0 - sqrt_u)
+            + self.vertices[1] * (sqrt_u * (1.0 - v))
+            + self.vertices[2] * (sqrt_u * v);
+        let uv = self.interpolate_uv(sqrt_u * (1.0 - v), sqrt_u * v);
+        let (dpdu, dpdv) = self.tangents();
+        Some(
+            HitRecord::new(
+                point,
+                self.geometric_normal().normalize(),
+                0.0,
+                true,
+                self.material.clone(),
+            )
+            .set_uv(uv.0, uv.1)
+            .set_tangents(dpdu, dpdv),
+        )
+    }
+
+    fn get_area(&self) -> f64 {
+        self.geometric_normal().len() / 2.0
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        self.get_intersection(&ray, 0.0, f64::INFINITY)
+            .and_then(|hit| self.solid_angle_pdf(origin, &hit.point))
+            .unwrap_or(0.0)
+    }
 }
 
 impl Triangle {
     /// Creates a new Triangle.
+    /// Its default material is diffuse, with a random color derived from the position of its vertices.
     pub fn new(vertices: [Vec3; 3], normal: Vec3) -> Self {
+        let coordinates: Vec<u64> = vertices.iter().flat_map(|v| v.to_bits()).collect();
+        let mut rng = seeded_rng(&coordinates);
         Triangle {
             vertices,
             normal,
-            material: Arc::new(Diffuse::new(Color::random())),
+            material: Arc::new(Diffuse::new(Color::random(&mut rng))),
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
         }
     }
 
@@ -99,4 +154,62 @@ impl Triangle {
     pub fn set_material(&mut self, material: Arc<dyn Material + Send + Sync + 'static>) {
         self.material = material;
     }
+
+    /// Sets the texture coordinates of the vertices of the Triangle, interpolated at the hits.
+    /// By default they are (0,0), (1,0) and (0,1), so that the texture coordinates of a hit are its barycentric coordinates.
+    pub fn set_uvs(&mut self, uvs: [(f64, f64); 3]) {
+        self.uvs = uvs;
+    }
+
+    /// Returns the texture coordinates of the point of barycentric coordinates (b1,b2), the weights of the second and third vertices.
+    fn interpolate_uv(&self, b1: f64, b2: f64) -> (f64, f64) {
+        let b0 = 1.0 - b1 - b2;
+        let [uv0, uv1, uv2] = self.uvs;
+        (
+            b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+            b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+        )
+    }
+
+    /// Returns the derivatives of the points of the Triangle with respect to the texture coordinates.
+    /// If the texture coordinates of the vertices are degenerate, any tangent frame of the Triangle is returned.
+    fn tangents(&self) -> (Vec3, Vec3) {
+        let [uv0, uv1, uv2] = self.uvs;
+        let (du02, dv02) = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+        let (du12, dv12) = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+        let dp02 = self.vertices[0] - self.vertices[2];
+        let dp12 = self.vertices[1] - self.vertices[2];
+        let det = du02 * dv12 - dv02 * du12;
+        if det.abs() < 1e-12 {
+            let base = Base::from_w(self.geometric_normal());
+            return (base.u(), base.v());
+        }
+        let inv_det = 1.0 / det;
+        (
+            (dp02 * dv12 - dp12 * dv02) * inv_det,
+            (dp12 * du02 - dp02 * du12) * inv_det,
+        )
+    }
+
+    /// Converts the uniform density on the area of the Triangle to a density in solid angle, for the point seen from origin.
+    /// Triangles can only be hit from their front side, None is returned if the point is seen from the back.
+    fn solid_angle_pdf(&self, origin: &Vec3, point: &Vec3) -> Option<f64> {
+        let geometric_normal = self.geometric_normal();
+        let area = geometric_normal.len() / 2.0;
+        let to_point = *point - *origin;
+        let distance_squared = to_point.len_squared();
+        let cos_light = -dot(&to_point, &geometric_normal.normalize()) / distance_squared.sqrt();
+        if cos_light <= 0.0 || area <= 0.0 {
+            return None;
+        }
+        Some(distance_squared / (cos_light * area))
+    }
+
+    /// Returns the normal of the front side of the Triangle, the side it can be hit from, whose length is twice its area.
+    fn geometric_normal(&self) -> Vec3 {
+        cross(
+            &(self.vertices[1] - self.vertices[0]),
+            &(self.vertices[2] - self.vertices[0]),
+        )
+    }
 }
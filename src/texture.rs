@@ -0,0 +1,540 @@
+use std::f64::consts::PI;
+
+use crate::color_management::TransferFunction;
+use crate::sampler::hash;
+use crate::utils::{dot, Color, Vec3};
+
+type BoxTexture = Box<dyn Texture + Send + Sync>;
+
+/// Implement this trait for the patterns of colors that can be applied on the Materials.
+///
+/// A Texture is sampled at the texture coordinates (u,v) reported by the shape at the hit, each between 0 and 1,
+/// or directly at the point of the hit for the solid textures. A Color is a Texture of a single color.
+pub trait Texture {
+    /// Returns the color of the Texture at the texture coordinates (u,v) of the point.
+    fn get_color(&self, u: f64, v: f64, point: &Vec3) -> Color;
+}
+
+impl Texture for Color {
+    fn get_color(&self, _: f64, _: f64, _: &Vec3) -> Color {
+        *self
+    }
+}
+
+/// How the texture coordinates outside of [0, 1] are mapped on an ImageTexture.
+///
+/// Repeat tiles the image, Clamp stretches the pixels of its borders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+}
+
+/// A Texture loaded from an image file (PNG, JPEG...), with bilinear filtering.
+///
+/// The bottom left corner of the image is at the texture coordinates (0,0), and the top right one at (1,1).
+/// By default the image repeats itself and its colors are decoded from sRGB, like the colors of most images.
+///
+/// # Example
+/// ```no_run
+/// # use raytracer::material::Diffuse;
+/// # use raytracer::texture::{ImageTexture, WrapMode};
+/// let label = ImageTexture::new("textures/label.png").set_wrap_mode(WrapMode::Clamp);
+/// let material = Diffuse::new(label);
+/// ```
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 3]>,
+    wrap_mode: WrapMode,
+    /// Linear value of each 8 bits value of the image
+    decoded: Vec<f64>,
+}
+
+impl ImageTexture {
+    /// Loads the image at path as an ImageTexture.
+    pub fn new(path: &str) -> Self {
+        let image = image::open(path).expect("Error opening image").to_rgb8();
+        let (width, height) = image.dimensions();
+        ImageTexture {
+            width,
+            height,
+            pixels: image.pixels().map(|pixel| pixel.0).collect(),
+            wrap_mode: WrapMode::Repeat,
+            decoded: vec![],
+        }
+        .set_transfer_function(TransferFunction::Srgb)
+    }
+
+    /// Sets how the texture coordinates outside of [0, 1] are mapped on the image.
+    pub fn set_wrap_mode(mut self, wrap_mode: WrapMode) -> Self {
+        self.wrap_mode = wrap_mode;
+        self
+    }
+
+    /// Sets the transfer function the values of the image are encoded with.
+    /// Use TransferFunction::Linear for the images that hold data rather than colors.
+    pub fn set_transfer_function(mut self, transfer_function: TransferFunction) -> Self {
+        self.decoded = (0..=255)
+            .map(|value| transfer_function.decode(value as f64 / 255.0))
+            .collect();
+        self
+    }
+
+    /// Returns the color of the pixel at column x and row y of the image, counted from its bottom left corner.
+    fn get_pixel(&self, x: i64, y: i64) -> Color {
+        let wrap = |value: i64, size: u32| match self.wrap_mode {
+            WrapMode::Repeat => value.rem_euclid(size as i64) as u32,
+            WrapMode::Clamp => value.clamp(0, size as i64 - 1) as u32,
+        };
+        let (x, y) = (wrap(x, self.width), wrap(y, self.height));
+        let [r, g, b] = self.pixels[((self.height - 1 - y) * self.width + x) as usize];
+        Color {
+            r: self.decoded[r as usize],
+            g: self.decoded[g as usize],
+            b: self.decoded[b as usize],
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn get_color(&self, u: f64, v: f64, _: &Vec3) -> Color {
+        // Position in pixels, relative to the centers of the pixels
+        let x = u * self.width as f64 - 0.5;
+        let y = v * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        self.get_pixel(x0, y0) * ((1.0 - dx) * (1.0 - dy))
+            + self.get_pixel(x0 + 1, y0) * (dx * (1.0 - dy))
+            + self.get_pixel(x0, y0 + 1) * ((1.0 - dx) * dy)
+            + self.get_pixel(x0 + 1, y0 + 1) * (dx * dy)
+    }
+}
+
+/// The coordinates the procedural textures are evaluated at.
+///
+/// Point evaluates them at the position of the hit in the Scene, like a pattern carved in a solid block.
+/// Uv evaluates them at the point (u, v, 0), following the texture coordinates of the surface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coordinates {
+    Point,
+    Uv,
+}
+
+impl Coordinates {
+    fn get_position(&self, u: f64, v: f64, point: &Vec3) -> Vec3 {
+        match self {
+            Coordinates::Point => *point,
+            Coordinates::Uv => Vec3::new(u, v, 0.0),
+        }
+    }
+}
+
+/// A 3D checkerboard alternating two Textures in cubes of the given size.
+///
+/// # Example
+/// ```
+/// # use raytracer::material::Diffuse;
+/// # use raytracer::texture::Checker;
+/// # use raytracer::utils::Color;
+/// let ground = Diffuse::new(Checker::new(Color::new(20, 20, 20), Color::new(230, 230, 230), 0.5));
+/// ```
+pub struct Checker {
+    even: BoxTexture,
+    odd: BoxTexture,
+    size: f64,
+    coordinates: Coordinates,
+}
+
+impl Checker {
+    /// Creates a new Checker of cubes of the given size, evaluated at the point of the hit.
+    pub fn new<A, B>(even: A, odd: B, size: f64) -> Self
+    where
+        A: Texture + Send + Sync + 'static,
+        B: Texture + Send + Sync + 'static,
+    {
+        if size <= 0.0 {
+            panic!("The size of the squares of a Checker must be positive");
+        }
+        Checker {
+            even: Box::new(even),
+            odd: Box::new(odd),
+            size,
+            coordinates: Coordinates::Point,
+        }
+    }
+
+    /// Sets the coordinates the Checker is evaluated at.
+    pub fn set_coordinates(mut self, coordinates: Coordinates) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+}
+
+impl Texture for Checker {
+    fn get_color(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        let position = self.coordinates.get_position(u, v, point);
+        let cell = |value: f64| (value / self.size).floor() as i64;
+        if (cell(position.x) + cell(position.y) + cell(position.z)).rem_euclid(2) == 0 {
+            self.even.get_color(u, v, point)
+        } else {
+            self.odd.get_color(u, v, point)
+        }
+    }
+}
+
+/// Perlin noise blending two colors, with features of about 1 / scale.
+///
+/// # Example
+/// ```
+/// # use raytracer::texture::Noise;
+/// # use raytracer::utils::Color;
+/// let stone = Noise::new(Color::new(90, 90, 85), Color::new(160, 155, 150), 4.0).set_seed(7);
+/// ```
+pub struct Noise {
+    low: Color,
+    high: Color,
+    scale: f64,
+    seed: u64,
+    coordinates: Coordinates,
+}
+
+impl Noise {
+    /// Creates a new Noise going from the low color to the high one, evaluated at the point of the hit.
+    pub fn new(low: Color, high: Color, scale: f64) -> Self {
+        Noise {
+            low,
+            high,
+            scale,
+            seed: 0,
+            coordinates: Coordinates::Point,
+        }
+    }
+
+    /// Sets the seed of the noise. Two textures of different seeds show different patterns.
+    pub fn set_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the coordinates the Noise is evaluated at.
+    pub fn set_coordinates(mut self, coordinates: Coordinates) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+}
+
+impl Texture for Noise {
+    fn get_color(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        let position = self.coordinates.get_position(u, v, point) * self.scale;
+        let t = 0.5 * (1.0 + perlin(&position, self.seed));
+        mix(self.low, self.high, t)
+    }
+}
+
+/// Turbulence blending two colors: the sum of octaves of the absolute value of Perlin noise,
+/// each twice as detailed and half as strong as the previous one.
+///
+/// # Example
+/// ```
+/// # use raytracer::texture::Turbulence;
+/// # use raytracer::utils::Color;
+/// let smoke = Turbulence::new(Color::new(40, 40, 40), Color::new(220, 220, 220), 2.0).set_octaves(5);
+/// ```
+pub struct Turbulence {
+    low: Color,
+    high: Color,
+    scale: f64,
+    octaves: u32,
+    seed: u64,
+    coordinates: Coordinates,
+}
+
+impl Turbulence {
+    /// Creates a new Turbulence of 7 octaves going from the low color to the high one, evaluated at the point of the hit.
+    pub fn new(low: Color, high: Color, scale: f64) -> Self {
+        Turbulence {
+            low,
+            high,
+            scale,
+            octaves: 7,
+            seed: 0,
+            coordinates: Coordinates::Point,
+        }
+    }
+
+    /// Sets the number of octaves of noise summed by the Turbulence.
+    pub fn set_octaves(mut self, octaves: u32) -> Self {
+        if octaves == 0 {
+            panic!("A Turbulence needs at least one octave");
+        }
+        self.octaves = octaves;
+        self
+    }
+
+    /// Sets the seed of the noise. Two textures of different seeds show different patterns.
+    pub fn set_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the coordinates the Turbulence is evaluated at.
+    pub fn set_coordinates(mut self, coordinates: Coordinates) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+}
+
+impl Texture for Turbulence {
+    fn get_color(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        let position = self.coordinates.get_position(u, v, point) * self.scale;
+        let t = turbulence(&position, self.octaves, self.seed);
+        mix(self.low, self.high, t)
+    }
+}
+
+/// Marble, made of parallel veins along the x axis distorted by turbulence.
+///
+/// scale is the number of veins per unit of distance, and the turbulence is the strength of their distortion.
+///
+/// # Example
+/// ```
+/// # use raytracer::texture::Marble;
+/// # use raytracer::utils::Color;
+/// let marble = Marble::new(Color::new(235, 235, 230), Color::new(60, 60, 70), 2.0).set_turbulence(8.0);
+/// ```
+pub struct Marble {
+    base: Color,
+    vein: Color,
+    scale: f64,
+    turbulence: f64,
+    seed: u64,
+    coordinates: Coordinates,
+}
+
+impl Marble {
+    /// Creates a new Marble of the base color with veins of the vein color, evaluated at the point of the hit.
+    pub fn new(base: Color, vein: Color, scale: f64) -> Self {
+        Marble {
+            base,
+            vein,
+            scale,
+            turbulence: 5.0,
+            seed: 0,
+            coordinates: Coordinates::Point,
+        }
+    }
+
+    /// Sets the strength of the distortion of the veins.
+    pub fn set_turbulence(mut self, turbulence: f64) -> Self {
+        self.turbulence = turbulence;
+        self
+    }
+
+    /// Sets the seed of the noise. Two textures of different seeds show different patterns.
+    pub fn set_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the coordinates the Marble is evaluated at.
+    pub fn set_coordinates(mut self, coordinates: Coordinates) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+}
+
+impl Texture for Marble {
+    fn get_color(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        let position = self.coordinates.get_position(u, v, point) * self.scale;
+        let distortion = self.turbulence * turbulence(&position, 7, self.seed);
+        // Sharp veins where the sine is close to zero
+        let t = (PI * position.x + distortion).sin().abs().powf(0.3);
+        mix(self.vein, self.base, t)
+    }
+}
+
+/// Wood, made of rings around the y axis distorted by turbulence.
+///
+/// ring_spacing is the distance between two rings, and the turbulence is the strength of their distortion.
+///
+/// # Example
+/// ```
+/// # use raytracer::texture::Wood;
+/// # use raytracer::utils::Color;
+/// let oak = Wood::new(Color::new(190, 140, 90), Color::new(120, 75, 40), 0.1);
+/// ```
+pub struct Wood {
+    light: Color,
+    dark: Color,
+    ring_spacing: f64,
+    turbulence: f64,
+    seed: u64,
+    coordinates: Coordinates,
+}
+
+impl Wood {
+    /// Creates a new Wood whose rings go from the light color to the dark one, evaluated at the point of the hit.
+    pub fn new(light: Color, dark: Color, ring_spacing: f64) -> Self {
+        if ring_spacing <= 0.0 {
+            panic!("The ring spacing of a Wood must be positive");
+        }
+        Wood {
+            light,
+            dark,
+            ring_spacing,
+            turbulence: 0.5,
+            seed: 0,
+            coordinates: Coordinates::Point,
+        }
+    }
+
+    /// Sets the strength of the distortion of the rings, in number of rings.
+    pub fn set_turbulence(mut self, turbulence: f64) -> Self {
+        self.turbulence = turbulence;
+        self
+    }
+
+    /// Sets the seed of the noise. Two textures of different seeds show different patterns.
+    pub fn set_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the coordinates the Wood is evaluated at.
+    pub fn set_coordinates(mut self, coordinates: Coordinates) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+}
+
+impl Texture for Wood {
+    fn get_color(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        let position = self.coordinates.get_position(u, v, point) / self.ring_spacing;
+        let radius = (position.x * position.x + position.z * position.z).sqrt();
+        let rings = radius + self.turbulence * turbulence(&(position * 0.25), 4, self.seed);
+        // Each ring slowly darkens, then quickly goes back to the light color
+        let t = rings.fract().powi(3);
+        mix(self.light, self.dark, t)
+    }
+}
+
+/// Linear interpolation from color a (t = 0) to color b (t = 1).
+fn mix(a: Color, b: Color, t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    a * (1.0 - t) + b * t
+}
+
+/// Perlin gradient noise, between -1 and 1, null at the integer coordinates.
+fn perlin(position: &Vec3, seed: u64) -> f64 {
+    let (x0, y0, z0) = (position.x.floor(), position.y.floor(), position.z.floor());
+    let offset = Vec3::new(position.x - x0, position.y - y0, position.z - z0);
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (u, v, w) = (fade(offset.x), fade(offset.y), fade(offset.z));
+
+    // Dot product of the gradient of a corner of the cell with the offset to this corner
+    let corner = |dx: f64, dy: f64, dz: f64| {
+        let h = hash(&[
+            seed,
+            (x0 + dx) as i64 as u64,
+            (y0 + dy) as i64 as u64,
+            (z0 + dz) as i64 as u64,
+        ]);
+        dot(
+            &GRADIENTS[(h % 12) as usize],
+            &(offset - Vec3::new(dx, dy, dz)),
+        )
+    };
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    lerp(
+        lerp(
+            lerp(corner(0.0, 0.0, 0.0), corner(1.0, 0.0, 0.0), u),
+            lerp(corner(0.0, 1.0, 0.0), corner(1.0, 1.0, 0.0), u),
+            v,
+        ),
+        lerp(
+            lerp(corner(0.0, 0.0, 1.0), corner(1.0, 0.0, 1.0), u),
+            lerp(corner(0.0, 1.0, 1.0), corner(1.0, 1.0, 1.0), u),
+            v,
+        ),
+        w,
+    )
+    .clamp(-1.0, 1.0)
+}
+
+/// Sum of octaves of the absolute value of Perlin noise, between 0 and about 1.
+fn turbulence(position: &Vec3, octaves: u32, seed: u64) -> f64 {
+    let (mut sum, mut amplitude, mut frequency) = (0.0, 1.0, 1.0);
+    for octave in 0..octaves {
+        sum += amplitude * perlin(&(*position * frequency), seed + octave as u64).abs();
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum
+}
+
+/// Gradients of the Perlin noise: the directions from the center of a cube to the middle of its edges.
+const GRADIENTS: [Vec3; 12] = [
+    Vec3 {
+        x: 1.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: -1.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: -1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: -1.0,
+        y: -1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: 0.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: -1.0,
+        y: 0.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: 1.0,
+        y: 0.0,
+        z: -1.0,
+    },
+    Vec3 {
+        x: -1.0,
+        y: 0.0,
+        z: -1.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: -1.0,
+        z: 1.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: -1.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: -1.0,
+        z: -1.0,
+    },
+];
@@ -0,0 +1,122 @@
+/// Order in which the tiles of the image are scheduled for rendering.
+///
+/// Scanline renders the tiles row by row starting from the top left corner.
+/// Spiral starts from the center of the image and spirals outwards, which gives a preview of the subject early.
+/// Hilbert follows a Hilbert curve, keeping consecutive tiles close to each other for a better cache usage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+/// A rectangular area of the image that is rendered by a single worker.
+#[derive(Clone, Copy, Debug)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Splits an image of size width x height in tiles of size tile_size x tile_size, sorted in the given order.
+/// Tiles on the right and bottom borders of the image may be smaller.
+///
+/// # Panics
+/// Panics if tile_size is zero.
+pub fn generate_tiles(width: u32, height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    if tile_size == 0 {
+        panic!("The tile size should be greater than zero");
+    }
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    let coordinates = match order {
+        TileOrder::Scanline => scanline_order(columns, rows),
+        TileOrder::Spiral => spiral_order(columns, rows),
+        TileOrder::Hilbert => hilbert_order(columns, rows),
+    };
+
+    coordinates
+        .into_iter()
+        .map(|(column, row)| {
+            let x = column * tile_size;
+            let y = row * tile_size;
+            Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+fn scanline_order(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let mut coordinates = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            coordinates.push((column, row));
+        }
+    }
+    coordinates
+}
+
+fn spiral_order(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = (columns * rows) as usize;
+    let mut coordinates = Vec::with_capacity(total);
+    if total == 0 {
+        return coordinates;
+    }
+
+    // Walk a square spiral around the central tile, skipping the positions outside of the grid
+    let (mut x, mut y) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut direction = 0;
+    let mut segment_length = 1;
+    loop {
+        for _ in 0..2 {
+            for _ in 0..segment_length {
+                if x >= 0 && y >= 0 && x < columns as i64 && y < rows as i64 {
+                    coordinates.push((x as u32, y as u32));
+                    if coordinates.len() == total {
+                        return coordinates;
+                    }
+                }
+                x += directions[direction].0;
+                y += directions[direction].1;
+            }
+            direction = (direction + 1) % 4;
+        }
+        segment_length += 1;
+    }
+}
+
+fn hilbert_order(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let size = columns.max(rows).next_power_of_two();
+    let mut coordinates = scanline_order(columns, rows);
+    coordinates.sort_by_key(|&(x, y)| hilbert_index(size, x, y));
+    coordinates
+}
+
+/// Returns the distance along the Hilbert curve filling a size x size grid of the cell (x, y).
+fn hilbert_index(size: u32, x: u32, y: u32) -> u64 {
+    let (mut x, mut y) = (x, y);
+    let mut index = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so that the curve is continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
@@ -0,0 +1,93 @@
+use crate::utils::Color;
+
+/// Operator mapping the high dynamic range colors of a render to the [0, 1] range of an 8 bits image.
+///
+/// None only clamps the colors, which makes bright areas blow out to flat white.
+/// Reinhard maps the luminance l of a pixel to l / (1 + l).
+/// ExtendedReinhard does the same but maps the luminance white to pure white instead of infinity. white must be positive.
+/// Hable is the filmic curve designed by John Hable for Uncharted 2.
+/// Aces is Krzysztof Narkowicz's fit of the ACES filmic curve.
+///
+/// # Example
+/// ```
+/// # use raytracer::tone_mapping::ToneMapping;
+/// # use raytracer::utils::Color;
+/// let color = ToneMapping::Reinhard.apply(Color::new(255, 255, 255) * 100.0);
+/// assert!(color.r < 1.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapping {
+    None,
+    Reinhard,
+    ExtendedReinhard { white: f64 },
+    Hable,
+    Aces,
+}
+
+impl ToneMapping {
+    /// Checks the parameters of the operator.
+    ///
+    /// # Panics
+    /// Panics if the white of ExtendedReinhard is NaN or not positive.
+    pub fn check(&self) {
+        if let ToneMapping::ExtendedReinhard { white } = self {
+            if white.is_nan() || *white <= 0.0 {
+                panic!("The white parameter of ExtendedReinhard should be positive as it is the luminance mapped to pure white");
+            }
+        }
+    }
+
+    /// Applies the tone mapping operator to a linear Color.
+    ///
+    /// # Panics
+    /// Panics if the white of ExtendedReinhard is NaN or not positive.
+    pub fn apply(&self, color: Color) -> Color {
+        match self {
+            ToneMapping::None => color,
+            ToneMapping::Reinhard => {
+                let l = color.luminance();
+                scale_luminance(color, l / (1.0 + l))
+            }
+            ToneMapping::ExtendedReinhard { white } => {
+                self.check();
+                let l = color.luminance();
+                scale_luminance(color, l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapping::Hable => {
+                // The linear white point of the curve, mapped to pure white
+                let white = 11.2;
+                let exposure_bias = 2.0;
+                let scale = 1.0 / hable(white);
+                map_channels(color, |c| hable(c * exposure_bias) * scale)
+            }
+            ToneMapping::Aces => map_channels(color, |c| {
+                let (a, b, c2, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                ((c * (a * c + b)) / (c * (c2 * c + d) + e)).clamp(0.0, 1.0)
+            }),
+        }
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+fn scale_luminance(color: Color, new_luminance: f64) -> Color {
+    let l = color.luminance();
+    if l <= 0.0 {
+        return color;
+    }
+    color * (new_luminance / l)
+}
+
+fn map_channels<F>(color: Color, f: F) -> Color
+where
+    F: Fn(f64) -> f64,
+{
+    Color {
+        r: f(color.r),
+        g: f(color.g),
+        b: f(color.b),
+    }
+}
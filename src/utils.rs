@@ -1,5 +1,9 @@
 use std::ops::{self, Index, IndexMut};
 
+use rand::Rng;
+
+use crate::color_management::TransferFunction;
+
 #[derive(Clone, Copy, Debug)]
 pub struct Vec3 {
     pub x: f64,
@@ -170,6 +174,11 @@ impl Vec3 {
         self /= self.len();
         self
     }
+
+    /// Returns the bit patterns of the coordinates, to be used as hash inputs.
+    pub(crate) fn to_bits(self) -> [u64; 3] {
+        [self.x.to_bits(), self.y.to_bits(), self.z.to_bits()]
+    }
 }
 
 pub struct Base {
@@ -189,6 +198,24 @@ impl Base {
         Base { u, v, w }
     }
 
+    /// Creates an orthonormal Base whose w vector is the given vector, normalized.
+    pub fn from_w(w: Vec3) -> Self {
+        let w = w.normalize();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = cross(&w, &a).normalize();
+        let u = cross(&v, &w);
+        Base { u, v, w }
+    }
+
+    /// Converts a vector expressed in this Base to world coordinates.
+    pub fn to_world(&self, local: Vec3) -> Vec3 {
+        self.u * local.x + self.v * local.y + self.w * local.z
+    }
+
     pub fn u(&self) -> Vec3 {
         self.u
     }
@@ -306,20 +333,30 @@ impl Color {
         }
     }
 
-    pub fn random() -> Self {
+    /// Returns a random Color drawn from the random number generator.
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
         Color {
-            r: rand::random(),
-            g: rand::random(),
-            b: rand::random(),
+            r: rng.gen(),
+            g: rng.gen(),
+            b: rng.gen(),
         }
     }
 
-    pub fn convert(&self, gamma_correction: f64) -> image::Rgb<u8> {
-        image::Rgb([
-            (self.r.min(1.0) * 255.0).powf(1.0 / gamma_correction) as u8,
-            (self.g.min(1.0) * 255.0).powf(1.0 / gamma_correction) as u8,
-            (self.b.min(1.0) * 255.0).powf(1.0 / gamma_correction) as u8,
-        ])
+    /// Returns the relative luminance of the Color.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Returns true if all the channels of the Color are zero.
+    pub fn is_black(&self) -> bool {
+        self.r == 0.0 && self.g == 0.0 && self.b == 0.0
+    }
+
+    /// Converts the linear Color to an 8 bits pixel.
+    /// The channels are clamped between 0.0 and 1.0 and encoded with the transfer function before being quantized.
+    pub fn convert(&self, transfer_function: &TransferFunction) -> image::Rgb<u8> {
+        let quantize = |c: f64| (transfer_function.encode(c.clamp(0.0, 1.0)) * 255.0).round() as u8;
+        image::Rgb([quantize(self.r), quantize(self.g), quantize(self.b)])
     }
 }
 
@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io::Read;
+
+use crate::sampler::hash;
+
+/// A 3D grid of densities, defining a density field inside a box.
+///
+/// The densities are stored at the nodes of a regular grid spanning the box from corner to corner,
+/// and trilinearly interpolated between them. Positions in the grid are given in local coordinates between 0 and 1.
+///
+/// # Example
+/// ```
+/// # use raytracer::voxel_grid::VoxelGrid;
+/// // A ball whose density fades from its center
+/// let grid = VoxelGrid::from_fn(33, 33, 33, |x, y, z| {
+///     let distance = ((x - 0.5).powi(2) + (y - 0.5).powi(2) + (z - 0.5).powi(2)).sqrt();
+///     (1.0 - 2.0 * distance).max(0.0)
+/// });
+/// assert_eq!(grid.get_density(0.5, 0.5, 0.5), 1.0);
+/// assert_eq!(grid.get_max_density(), 1.0);
+/// ```
+pub struct VoxelGrid {
+    size: [usize; 3],
+    densities: Vec<f64>,
+    max_density: f64,
+    /// Hash of the size and densities of the grid
+    key: u64,
+}
+
+impl VoxelGrid {
+    /// Creates a new VoxelGrid of nx * ny * nz nodes.
+    /// The densities are ordered with x varying the fastest, then y, then z.
+    pub fn new(nx: usize, ny: usize, nz: usize, densities: Vec<f64>) -> Self {
+        if nx < 2 || ny < 2 || nz < 2 {
+            panic!("A VoxelGrid needs at least 2 nodes along each axis");
+        }
+        if densities.len() != nx * ny * nz {
+            panic!(
+                "A VoxelGrid of {}x{}x{} nodes needs {} densities, got {}",
+                nx,
+                ny,
+                nz,
+                nx * ny * nz,
+                densities.len()
+            );
+        }
+        if densities.iter().any(|density| *density < 0.0) {
+            panic!("The densities of a VoxelGrid can't be negative");
+        }
+        let max_density = densities
+            .iter()
+            .fold(0.0, |max: f64, density| max.max(*density));
+        let mut values = vec![nx as u64, ny as u64, nz as u64];
+        values.extend(densities.iter().map(|density| density.to_bits()));
+        VoxelGrid {
+            size: [nx, ny, nz],
+            key: hash(&values),
+            densities,
+            max_density,
+        }
+    }
+
+    /// Creates a new VoxelGrid of nx * ny * nz nodes, whose densities are given by the function of their local coordinates.
+    pub fn from_fn<F>(nx: usize, ny: usize, nz: usize, density: F) -> Self
+    where
+        F: Fn(f64, f64, f64) -> f64,
+    {
+        let mut densities = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    densities.push(density(
+                        x as f64 / (nx - 1).max(1) as f64,
+                        y as f64 / (ny - 1).max(1) as f64,
+                        z as f64 / (nz - 1).max(1) as f64,
+                    ));
+                }
+            }
+        }
+        VoxelGrid::new(nx, ny, nz, densities)
+    }
+
+    /// Loads a VoxelGrid of nx * ny * nz nodes from a raw file of little-endian 32 bits floats,
+    /// ordered with x varying the fastest, then y, then z.
+    pub fn from_raw_file(path: &str, nx: usize, ny: usize, nz: usize) -> Self {
+        let mut bytes = vec![];
+        File::open(path)
+            .expect("Error opening file")
+            .read_to_end(&mut bytes)
+            .expect("Error reading file");
+        let densities = bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as f64)
+            .collect();
+        VoxelGrid::new(nx, ny, nz, densities)
+    }
+
+    /// Creates a cloud-like VoxelGrid of resolution nodes along each axis, from a fractal noise of the given frequency.
+    /// The density fades to zero toward the sides of the grid, so that the cloud doesn't show the shape of its box.
+    pub fn from_noise(resolution: usize, frequency: f64, seed: u64) -> Self {
+        VoxelGrid::from_fn(resolution, resolution, resolution, |x, y, z| {
+            let noise = fractal_noise(x * frequency, y * frequency, z * frequency, seed);
+            let distance = ((x - 0.5).powi(2) + (y - 0.5).powi(2) + (z - 0.5).powi(2)).sqrt();
+            let falloff = (1.0 - 2.0 * distance).max(0.0);
+            (2.0 * noise * falloff - 0.3).max(0.0)
+        })
+    }
+
+    /// Returns the density at the local coordinates (x,y,z), zero outside of the grid.
+    pub fn get_density(&self, x: f64, y: f64, z: f64) -> f64 {
+        let position = [x, y, z];
+        if position.iter().any(|p| !(0.0..=1.0).contains(p)) {
+            return 0.0;
+        }
+
+        // Lowest node of the cell holding the position, and offset in the cell
+        let mut node = [0; 3];
+        let mut offset = [0.0; 3];
+        for axis in 0..3 {
+            let cells = self.size[axis] - 1;
+            let p = position[axis] * cells as f64;
+            node[axis] = (p as usize).min(cells - 1);
+            offset[axis] = p - node[axis] as f64;
+        }
+
+        let mut density = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut corner_node = node;
+            for axis in 0..3 {
+                if corner & (1 << axis) != 0 {
+                    corner_node[axis] += 1;
+                    weight *= offset[axis];
+                } else {
+                    weight *= 1.0 - offset[axis];
+                }
+            }
+            if weight > 0.0 {
+                density += weight * self.densities[self.index(corner_node)];
+            }
+        }
+        density
+    }
+
+    /// Returns the highest density of the grid.
+    pub fn get_max_density(&self) -> f64 {
+        self.max_density
+    }
+
+    /// Returns a hash of the grid, which identifies it among the other grids.
+    pub(crate) fn get_key(&self) -> u64 {
+        self.key
+    }
+
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.size[1] + y) * self.size[0] + x
+    }
+}
+
+/// Sum of 5 octaves of value noise, between 0 and 1.
+fn fractal_noise(x: f64, y: f64, z: f64, seed: u64) -> f64 {
+    let (mut sum, mut amplitude, mut frequency, mut total) = (0.0, 1.0, 1.0, 0.0);
+    for octave in 0..5 {
+        sum += amplitude * value_noise(x * frequency, y * frequency, z * frequency, seed + octave);
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+/// Noise interpolating random values, between 0 and 1, given at the integer coordinates.
+fn value_noise(x: f64, y: f64, z: f64, seed: u64) -> f64 {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let (u, v, w) = (smooth(x - x0), smooth(y - y0), smooth(z - z0));
+    let value = |dx: f64, dy: f64, dz: f64| {
+        let h = hash(&[
+            seed,
+            (x0 + dx) as i64 as u64,
+            (y0 + dy) as i64 as u64,
+            (z0 + dz) as i64 as u64,
+        ]);
+        (h >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    lerp(
+        lerp(
+            lerp(value(0.0, 0.0, 0.0), value(1.0, 0.0, 0.0), u),
+            lerp(value(0.0, 1.0, 0.0), value(1.0, 1.0, 0.0), u),
+            v,
+        ),
+        lerp(
+            lerp(value(0.0, 0.0, 1.0), value(1.0, 0.0, 1.0), u),
+            lerp(value(0.0, 1.0, 1.0), value(1.0, 1.0, 1.0), u),
+            v,
+        ),
+        w,
+    )
+}